@@ -0,0 +1,233 @@
+//! Conversion of a whole targetdb `devices/` directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use xml::reader::EventReader;
+
+use crate::error::{Error, Result, Warning};
+use crate::reader::{open_tixml_file, read_cpus, CpuInfo};
+use crate::{process_device, Args};
+
+/// The outcome of converting one device file.
+pub struct BatchResult {
+    /// Device file name, without extension
+    pub device: String,
+    /// The SVD file, written only if the conversion succeeded
+    pub output: PathBuf,
+    pub warnings: Vec<Warning>,
+    pub result: Result<()>,
+}
+
+fn convert_device(
+    args: &Args,
+    input: &Path,
+    output: &Path,
+    device_header: Option<&str>,
+) -> Result<()> {
+    let fd_in = open_tixml_file(input)?;
+    let root_path = input.to_string_lossy();
+
+    // Convert into memory first, so that failures leave no partial files.
+    let mut svd = vec![];
+    process_device(args, fd_in, &root_path, device_header, &mut svd)?;
+    fs::write(output, svd).map_err(|e| Error::from(e).in_file(output))
+}
+
+/// Convert a device file into `out_dir/<name>.svd`, collecting the
+/// warnings.
+fn convert_into_dir(
+    args: &Args,
+    input: &Path,
+    name: String,
+    out_dir: &Path,
+    device_header: Option<&str>,
+) -> BatchResult {
+    let output = out_dir.join(format!("{}.svd", name));
+    if !args.silent {
+        eprintln!("Processing file: {}", input.display());
+    }
+
+    let result = convert_device(args, input, &output, device_header);
+    let warnings = args.take_warnings();
+    if !args.silent {
+        for warning in &warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    BatchResult {
+        device: name,
+        output,
+        warnings,
+        result,
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The CPU id, made safe for use in a file name.
+fn cpu_file_name(cpu: &CpuInfo) -> String {
+    if cpu.id.is_empty() {
+        return format!("cpu{}", cpu.index);
+    }
+    cpu.id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Convert each CPU of a device file into its own SVD file in `out_dir`,
+/// named after the device and the CPU id, such as
+/// `cc1352r1f3_CORTEX_M4_0.svd`.
+pub fn process_device_cpus(
+    args: &Args,
+    input: &Path,
+    out_dir: &Path,
+    device_header: Option<&str>,
+) -> Result<Vec<BatchResult>> {
+    let parser = EventReader::new(open_tixml_file(input)?);
+    let cpus = read_cpus(args, parser).map_err(|e| e.in_file(input))?;
+    fs::create_dir_all(out_dir)?;
+
+    // Warnings from listing the CPUs go with the first conversion
+    let mut warnings = args.take_warnings();
+    let mut results = vec![];
+    for cpu in &cpus {
+        let name = format!("{}_{}", file_stem(input), cpu_file_name(cpu));
+        let cpu_args = args.for_cpu(cpu.index);
+        warnings.drain(..).for_each(|w| cpu_args.warn(w));
+        results.push(convert_into_dir(
+            &cpu_args,
+            input,
+            name,
+            out_dir,
+            device_header,
+        ));
+    }
+    Ok(results)
+}
+
+/// Convert every device file in `devices_dir`, writing one SVD file per
+/// device, or one per CPU if selected in `args`, into `out_dir`.
+/// Peripheral files are expected in the sibling `Modules/` directory, as
+/// in a Code Composer installation.
+pub fn process_devices_dir(
+    args: &Args,
+    devices_dir: &Path,
+    out_dir: &Path,
+    device_header: Option<&str>,
+) -> Result<Vec<BatchResult>> {
+    let modules_dir = devices_dir.join("../Modules");
+    if !modules_dir.is_dir() && !args.silent {
+        eprintln!(
+            "No Modules directory next to {}; peripheral files will not be found",
+            devices_dir.display()
+        );
+    }
+    args.take_warnings();
+
+    let mut inputs = vec![];
+    for entry in fs::read_dir(devices_dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "xml") {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    fs::create_dir_all(out_dir)?;
+
+    let mut results = vec![];
+    for input in inputs {
+        let device = file_stem(&input);
+        if !args.all_cpus {
+            results.push(convert_into_dir(
+                args,
+                &input,
+                device,
+                out_dir,
+                device_header,
+            ));
+            continue;
+        }
+        match process_device_cpus(args, &input, out_dir, device_header) {
+            Ok(cpu_results) => results.extend(cpu_results),
+            Err(e) => results.push(BatchResult {
+                output: out_dir.join(format!("{}.svd", device)),
+                device,
+                warnings: args.take_warnings(),
+                result: Err(e),
+            }),
+        }
+    }
+    Ok(results)
+}
+
+/// Print a table with the outcome of each conversion, followed by totals.
+pub fn write_batch_summary<O>(results: &[BatchResult], out: &mut O) -> io::Result<()>
+where
+    O: io::Write,
+{
+    let width = results
+        .iter()
+        .map(|r| r.device.len())
+        .chain(Some("Device".len()))
+        .max()
+        .unwrap_or(0);
+    let mut ok = 0;
+    let mut warned = 0;
+    let mut failed = 0;
+
+    writeln!(
+        out,
+        "{:<width$}  {:<8}  {:>8}  Message",
+        "Device",
+        "Status",
+        "Warnings",
+        width = width
+    )?;
+    for r in results {
+        let (status, message) = match r.result {
+            Err(ref e) => {
+                failed += 1;
+                ("failed", e.to_string())
+            }
+            Ok(()) if !r.warnings.is_empty() => {
+                warned += 1;
+                ("warnings", r.warnings[0].to_string())
+            }
+            Ok(()) => {
+                ok += 1;
+                ("ok", String::new())
+            }
+        };
+        writeln!(
+            out,
+            "{:<width$}  {:<8}  {:>8}  {}",
+            r.device,
+            status,
+            r.warnings.len(),
+            message,
+            width = width
+        )?;
+    }
+    writeln!(
+        out,
+        "{} devices: {} ok, {} with warnings, {} failed",
+        results.len(),
+        ok,
+        warned,
+        failed
+    )
+}
@@ -0,0 +1,333 @@
+//! Writing the device model as a CMSIS-style C header.
+//!
+//! Each peripheral becomes a `<PERIPHERAL>_Type` struct, padded to the
+//! register offsets, with a pointer macro per instance. Each field gets
+//! `_Pos` and `_Msk` macros, and each enumerated value a define holding
+//! the value shifted into place.
+
+use std::io;
+
+use crate::error::Result;
+use crate::model::{Access, Device, Field, Peripheral, Register};
+use crate::reader::parse_number;
+use crate::Args;
+
+/// Make a C identifier from an SVD name, dropping array placeholders.
+fn c_name(name: &str) -> String {
+    let mut c_name: String = name
+        .replace("%s", "")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if c_name.is_empty() || c_name.starts_with(|c: char| c.is_ascii_digit()) {
+        c_name.insert(0, '_');
+    }
+    c_name
+}
+
+/// Make a one-line C comment text from a TIXML description.
+fn c_comment(description: &str) -> String {
+    description
+        .replace("#br#", " ")
+        .replace("*/", "* /")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn c_type(bits: u32) -> (&'static str, u64) {
+    match bits {
+        0..=8 => ("uint8_t", 1),
+        9..=16 => ("uint16_t", 2),
+        17..=32 => ("uint32_t", 4),
+        _ => ("uint64_t", 8),
+    }
+}
+
+fn c_qualifier(access: Option<Access>) -> &'static str {
+    match access {
+        Some(Access::ReadOnly) => "__IM ",
+        Some(Access::WriteOnly) => "__OM ",
+        _ => "__IOM",
+    }
+}
+
+/// A struct member: one register, or a plain C array of registers.
+struct Member<'a> {
+    name: String,
+    offset: u64,
+    count: u64,
+    c_type: &'static str,
+    bytes: u64,
+    register: &'a Register,
+}
+
+/// Lay out the registers of a peripheral, sorted by offset. Register
+/// arrays become C arrays if their elements are contiguous, and are
+/// expanded into single registers otherwise.
+fn members<'a>(device: &Device, peripheral: &'a Peripheral) -> Vec<Member<'a>> {
+    let mut members = vec![];
+    for register in &peripheral.registers {
+        let (c_type, bytes) = c_type(register.size.unwrap_or(device.size));
+        match register.dim {
+            Some(ref dim) if dim.dim_increment == bytes => members.push(Member {
+                name: c_name(&register.name),
+                offset: register.address_offset,
+                count: u64::from(dim.dim),
+                c_type,
+                bytes,
+                register,
+            }),
+            Some(ref dim) => {
+                for (i, index) in dim.indices().iter().enumerate() {
+                    members.push(Member {
+                        name: c_name(&register.name.replace("%s", index)),
+                        offset: register.address_offset + i as u64 * dim.dim_increment,
+                        count: 1,
+                        c_type,
+                        bytes,
+                        register,
+                    });
+                }
+            }
+            None => members.push(Member {
+                name: c_name(&register.name),
+                offset: register.address_offset,
+                count: 1,
+                c_type,
+                bytes,
+                register,
+            }),
+        }
+    }
+    members.sort_by_key(|m| m.offset);
+    members
+}
+
+fn write_member<O>(member: &Member, indent: &str, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    let array = if member.count > 1 {
+        format!("[{}]", member.count)
+    } else {
+        String::new()
+    };
+    let description = member.register.description.as_deref().unwrap_or("");
+    writeln!(
+        out,
+        "{}{} {} {}{};  /*!< (@ 0x{:08X}) {} */",
+        indent,
+        c_qualifier(member.register.access),
+        member.c_type,
+        member.name,
+        array,
+        member.offset,
+        c_comment(description)
+    )?;
+    Ok(())
+}
+
+/// Write the register struct of a peripheral. Registers at the same
+/// offset are put into a union.
+fn write_struct<O>(device: &Device, peripheral: &Peripheral, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    let name = c_name(&peripheral.name);
+    writeln!(out)?;
+    match peripheral.description {
+        Some(ref description) => writeln!(out, "/* {}: {} */", name, c_comment(description))?,
+        None => writeln!(out, "/* {} */", name)?,
+    }
+    writeln!(out, "typedef struct {{")?;
+
+    let members = members(device, peripheral);
+    let mut position = 0;
+    let mut reserved = 0;
+    let mut i = 0;
+    while i < members.len() {
+        let offset = members[i].offset;
+        let group = members[i..]
+            .iter()
+            .take_while(|m| m.offset == offset)
+            .count();
+        let group = &members[i..i + group];
+        i += group.len();
+
+        if offset < position {
+            for member in group {
+                writeln!(
+                    out,
+                    "  /* {} at 0x{:08X} overlaps the previous register */",
+                    member.name, offset
+                )?;
+            }
+            continue;
+        }
+        if offset > position {
+            writeln!(
+                out,
+                "  __IM  uint8_t RESERVED{}[0x{:X}];",
+                reserved,
+                offset - position
+            )?;
+            reserved += 1;
+        }
+        if group.len() == 1 {
+            write_member(&group[0], "  ", out)?;
+        } else {
+            writeln!(out, "  union {{")?;
+            for member in group {
+                write_member(member, "    ", out)?;
+            }
+            writeln!(out, "  }};")?;
+        }
+        position = offset + group.iter().map(|m| m.bytes * m.count).max().unwrap_or(0);
+    }
+
+    writeln!(out, "}} {}_Type;", name)?;
+    Ok(())
+}
+
+fn write_field_defines<O>(prefix: &str, field: &Field, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    let (offset, width) = match (field.bit_offset, field.bit_width) {
+        (Some(offset), Some(width))
+            if width > 0 && offset.checked_add(width).is_some_and(|end| end <= 64) =>
+        {
+            (offset, width)
+        }
+        _ => return Ok(()),
+    };
+    let prefix = format!("{}_{}", prefix, c_name(&field.name));
+    let suffix = if offset + width > 32 { "ULL" } else { "UL" };
+    let mask = u64::MAX >> (64 - width);
+
+    writeln!(out, "#define {}_Pos {}U", prefix, offset)?;
+    writeln!(
+        out,
+        "#define {}_Msk (0x{:X}{} << {}_Pos)",
+        prefix, mask, suffix, prefix
+    )?;
+    for value in field.all_enumerated_values() {
+        if value.name.is_empty() {
+            continue;
+        }
+        match parse_number("value", &value.value) {
+            Ok(number) => writeln!(
+                out,
+                "#define {}_{} (0x{:X}{} << {}_Pos)",
+                prefix,
+                c_name(&value.name),
+                number,
+                suffix,
+                prefix
+            )?,
+            Err(_) => writeln!(
+                out,
+                "/* {}_{}: value '{}' not supported */",
+                prefix, value.name, value.value
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn write_peripheral_defines<O>(peripheral: &Peripheral, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    let name = c_name(&peripheral.name);
+    for register in &peripheral.registers {
+        if register.fields.is_empty() {
+            continue;
+        }
+        let prefix = format!("{}_{}", name, c_name(&register.name));
+        writeln!(out)?;
+        writeln!(out, "/* {} {} */", name, c_name(&register.name))?;
+        for field in &register.fields {
+            write_field_defines(&prefix, field, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a device as a C header.
+pub fn write_c_header<O>(args: &Args, device: &Device, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    let guard = format!("{}_H", c_name(&device.name).to_uppercase());
+    writeln!(
+        out,
+        "/* Created by tixml2svd; https://github.com/dhoove/tixml2svd */"
+    )?;
+    writeln!(
+        out,
+        "/* {}: {} */",
+        device.name,
+        c_comment(&device.description)
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}", guard)?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    for (qualifier, definition) in &[
+        ("__IM", "volatile const"),
+        ("__OM", "volatile"),
+        ("__IOM", "volatile"),
+    ] {
+        writeln!(out, "#ifndef {}", qualifier)?;
+        writeln!(out, "#define {} {}", qualifier, definition)?;
+        writeln!(out, "#endif")?;
+    }
+
+    for peripheral in &device.peripherals {
+        if args.verbose > 0 {
+            eprintln!("Writing C declarations for peripheral {}", peripheral.name);
+        }
+        if peripheral.derived_from.is_none() && !peripheral.registers.is_empty() {
+            write_struct(device, peripheral, out)?;
+        }
+    }
+
+    writeln!(out)?;
+    for peripheral in &device.peripherals {
+        let name = c_name(&peripheral.name);
+        let base_address = match peripheral.base_address {
+            Some(base_address) => base_address,
+            None => continue,
+        };
+        writeln!(out, "#define {}_BASE 0x{:08X}UL", name, base_address)?;
+        // Peripherals without registers, and those derived from them,
+        // have no struct type
+        let original = match peripheral.derived_from {
+            Some(ref original) => device.peripherals.iter().find(|p| &p.name == original),
+            None => Some(peripheral),
+        };
+        let type_name = match original {
+            Some(original) if !original.registers.is_empty() => c_name(&original.name),
+            _ => continue,
+        };
+        writeln!(
+            out,
+            "#define {} (({}_Type *) {}_BASE)",
+            name, type_name, name
+        )?;
+    }
+
+    for peripheral in &device.peripherals {
+        if peripheral.derived_from.is_none() {
+            write_peripheral_defines(peripheral, out)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "#endif /* {} */", guard)?;
+    Ok(())
+}
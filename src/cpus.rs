@@ -0,0 +1,84 @@
+//! Known CPU information for TI parts.
+//!
+//! TIXML device files only name the instruction set of a CPU, so the
+//! remaining SVD `<cpu>` information is looked up here, first by device
+//! id and then by instruction set.
+
+use crate::error::Warning;
+use crate::model::Device;
+use crate::Args;
+
+struct KnownCpu {
+    /// Lower-case device id prefixes; empty to match on `isa` alone
+    devices: &'static [&'static str],
+    /// TIXML `isa` attribute, or its prefix when matching a device id
+    isa: &'static str,
+    name: &'static str,
+    mpu_present: bool,
+    fpu_present: bool,
+    nvic_prio_bits: u32,
+    /// Bus width, also used as the default register size
+    width: u32,
+}
+
+#[rustfmt::skip]
+const KNOWN_CPUS: &[KnownCpu] = &[
+    // CC13x0 and CC26x0, Cortex-M3
+    KnownCpu { devices: &["cc1310", "cc1350", "cc2620", "cc2630", "cc2640", "cc2650"], isa: "Cortex_M3", name: "CM3", mpu_present: true, fpu_present: false, nvic_prio_bits: 3, width: 32 },
+    // CC13x2 and CC26x2, Cortex-M4F
+    KnownCpu { devices: &["cc1312", "cc1352", "cc2642", "cc2652"], isa: "Cortex_M4", name: "CM4", mpu_present: true, fpu_present: true, nvic_prio_bits: 3, width: 32 },
+    // CC2538, Cortex-M3
+    KnownCpu { devices: &["cc2538", "cm2538"], isa: "Cortex_M3", name: "CM3", mpu_present: true, fpu_present: false, nvic_prio_bits: 3, width: 32 },
+    // MSP432P4 and MSP432E4, Cortex-M4F
+    KnownCpu { devices: &["msp432p", "msp432e"], isa: "Cortex_M4", name: "CM4", mpu_present: true, fpu_present: true, nvic_prio_bits: 3, width: 32 },
+    // MSP430 and MSP430X
+    KnownCpu { devices: &["msp430", "cc430", "rf430"], isa: "MSP430", name: "other", mpu_present: false, fpu_present: false, nvic_prio_bits: 0, width: 16 },
+    // Fall back on the instruction set for other parts
+    KnownCpu { devices: &[], isa: "Cortex_M0", name: "CM0", mpu_present: false, fpu_present: false, nvic_prio_bits: 2, width: 32 },
+    KnownCpu { devices: &[], isa: "Cortex_M0P", name: "CM0PLUS", mpu_present: false, fpu_present: false, nvic_prio_bits: 2, width: 32 },
+    KnownCpu { devices: &[], isa: "Cortex_M3", name: "CM3", mpu_present: true, fpu_present: false, nvic_prio_bits: 3, width: 32 },
+    KnownCpu { devices: &[], isa: "Cortex_M4", name: "CM4", mpu_present: true, fpu_present: false, nvic_prio_bits: 3, width: 32 },
+    KnownCpu { devices: &[], isa: "Cortex_M4F", name: "CM4", mpu_present: true, fpu_present: true, nvic_prio_bits: 3, width: 32 },
+    KnownCpu { devices: &[], isa: "MSP430", name: "other", mpu_present: false, fpu_present: false, nvic_prio_bits: 0, width: 16 },
+    KnownCpu { devices: &[], isa: "MSP430X", name: "other", mpu_present: false, fpu_present: false, nvic_prio_bits: 0, width: 16 },
+];
+
+fn find_known_cpu(device_id: &str, isa: &str) -> Option<&'static KnownCpu> {
+    let device_id = device_id.to_lowercase();
+    KNOWN_CPUS
+        .iter()
+        .find(|k| {
+            (isa.is_empty() || isa.starts_with(k.isa))
+                && k.devices.iter().any(|d| device_id.starts_with(d))
+        })
+        .or_else(|| {
+            KNOWN_CPUS
+                .iter()
+                .find(|k| k.devices.is_empty() && k.isa == isa)
+        })
+}
+
+/// Fill in the CPU information for a known device or instruction set.
+pub(crate) fn apply_known_cpu(args: &Args, device: &mut Device, isa: &str) {
+    let known = match find_known_cpu(&device.name, isa) {
+        Some(known) => known,
+        None => {
+            args.warn(Warning::UnknownCpu {
+                context: Box::default(),
+                device: device.name.clone(),
+                isa: isa.to_string(),
+            });
+            return;
+        }
+    };
+
+    device.cpu.name = known.name.to_string();
+    // All supported TI parts are little-endian
+    device.cpu.endian = "little".to_string();
+    device.cpu.mpu_present = known.mpu_present;
+    device.cpu.fpu_present = known.fpu_present;
+    device.cpu.nvic_prio_bits = known.nvic_prio_bits;
+    device.width = known.width;
+    device.size = known.width;
+    device.reset_mask = (1u64 << known.width) - 1;
+}
@@ -0,0 +1,290 @@
+//! Sharing identical definitions through SVD `derivedFrom`.
+
+use crate::model::{Device, Peripheral};
+
+/// Whether two peripherals differ in no more than name, description and
+/// base address.
+fn same_layout(a: &Peripheral, b: &Peripheral) -> bool {
+    a.value == b.value
+        && a.group_name == b.group_name
+        && a.size == b.size
+        && a.registers == b.registers
+}
+
+/// Mark each peripheral that is identical to an earlier one, apart from
+/// name, description and base address, as derived from it.
+pub fn derive_peripherals(device: &mut Device) {
+    for i in 1..device.peripherals.len() {
+        let (originals, rest) = device.peripherals.split_at_mut(i);
+        let peripheral = &mut rest[0];
+        if peripheral.derived_from.is_some() {
+            continue;
+        }
+        if let Some(original) = originals
+            .iter()
+            .find(|o| o.derived_from.is_none() && same_layout(o, peripheral))
+        {
+            peripheral.derived_from = Some(original.name.clone());
+        }
+    }
+}
+
+/// Let a field derive its enumerated values from the first other field
+/// of the peripheral with the same ones and the same access, which gets
+/// a name for them.
+/// Fields of register arrays are not derived from, as their path has no
+/// single register name. Returns the path of the enumerated values
+/// derived from, such as `CTL.MODE.MODE`.
+pub(crate) fn derive_field_enumerated_values(
+    peripheral: &mut Peripheral,
+    register: usize,
+    field: usize,
+) -> Option<String> {
+    let this = &peripheral.registers[register].fields[field];
+    // A path names only one of separate read and write values
+    if this.enumerated_values.is_empty() || !this.write_enumerated_values.is_empty() {
+        return None;
+    }
+    let (r, f) = peripheral
+        .registers
+        .iter()
+        .enumerate()
+        .filter(|(_, reg)| reg.dim.is_none())
+        .flat_map(|(r, reg)| {
+            reg.fields
+                .iter()
+                .enumerate()
+                .map(move |(f, fld)| (r, f, fld))
+        })
+        .find(|(r, f, other)| {
+            (*r, *f) != (register, field)
+                && other.enumerated_values_derived_from.is_none()
+                && other.write_enumerated_values.is_empty()
+                && other.access == this.access
+                && other.enumerated_values == this.enumerated_values
+        })
+        .map(|(r, f, _)| (r, f))?;
+
+    let original = &mut peripheral.registers[r].fields[f];
+    let field_name = original.name.clone();
+    let name = original
+        .enumerated_values_name
+        .get_or_insert(field_name.clone())
+        .clone();
+    let path = format!("{}.{}.{}", peripheral.registers[r].name, field_name, name);
+    peripheral.registers[register].fields[field].enumerated_values_derived_from =
+        Some(path.clone());
+    Some(path)
+}
+
+/// Write each set of enumerated values that several fields of a
+/// peripheral have only once, for the first of these fields, and let
+/// the others derive from it.
+pub fn derive_enumerated_values(peripheral: &mut Peripheral) {
+    let fields: Vec<(usize, usize)> = peripheral
+        .registers
+        .iter()
+        .enumerate()
+        .flat_map(|(r, reg)| (0..reg.fields.len()).map(move |f| (r, f)))
+        .collect();
+    // From the last field back, so that the first field of each set
+    // is the last one left to derive from
+    for (r, f) in fields.into_iter().rev() {
+        if peripheral.registers[r].fields[f]
+            .enumerated_values_derived_from
+            .is_none()
+        {
+            derive_field_enumerated_values(peripheral, r, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Access, EnumeratedValue, Field, Register};
+
+    fn peripheral(name: &str, base_address: u64, register: &str) -> Peripheral {
+        Peripheral {
+            name: name.to_string(),
+            description: Some("Serial port".to_string()),
+            group_name: Some("UART".to_string()),
+            base_address: Some(base_address),
+            size: Some(0x1000),
+            registers: vec![Register {
+                name: register.to_string(),
+                ..Register::default()
+            }],
+            ..Peripheral::default()
+        }
+    }
+
+    fn derived_from(peripherals: Vec<Peripheral>) -> Vec<Option<String>> {
+        let mut device = Device {
+            peripherals,
+            ..Device::default()
+        };
+        derive_peripherals(&mut device);
+        device
+            .peripherals
+            .into_iter()
+            .map(|p| p.derived_from)
+            .collect()
+    }
+
+    #[test]
+    fn derive_identical_peripherals() {
+        let derived = derived_from(vec![
+            peripheral("UART0", 0x4000_0000, "DR"),
+            peripheral("UART1", 0x4000_1000, "DR"),
+            peripheral("UART2", 0x4000_2000, "DR"),
+        ]);
+        let uart0 = Some("UART0".to_string());
+        assert_eq!(derived, [None, uart0.clone(), uart0]);
+    }
+
+    #[test]
+    fn derive_despite_description() {
+        let mut uart1 = peripheral("UART1", 0x4000_1000, "DR");
+        uart1.description = Some("Serial port with flow control".to_string());
+        let derived = derived_from(vec![peripheral("UART0", 0x4000_0000, "DR"), uart1]);
+        assert_eq!(derived, [None, Some("UART0".to_string())]);
+    }
+
+    #[test]
+    fn keep_other_layouts() {
+        let mut other_group = peripheral("UART2", 0x4000_2000, "DR");
+        other_group.group_name = Some("SSI".to_string());
+        let derived = derived_from(vec![
+            peripheral("UART0", 0x4000_0000, "DR"),
+            peripheral("UART1", 0x4000_1000, "CR"),
+            other_group,
+        ]);
+        assert_eq!(derived, [None, None, None]);
+    }
+
+    #[test]
+    fn derive_from_first_of_several_layouts() {
+        let derived = derived_from(vec![
+            peripheral("UART0", 0x4000_0000, "DR"),
+            peripheral("SSI0", 0x4000_1000, "CR"),
+            peripheral("SSI1", 0x4000_2000, "CR"),
+            peripheral("UART1", 0x4000_3000, "DR"),
+        ]);
+        assert_eq!(
+            derived,
+            [
+                None,
+                None,
+                Some("SSI0".to_string()),
+                Some("UART0".to_string())
+            ]
+        );
+    }
+
+    fn value(name: &str, value: &str) -> EnumeratedValue {
+        EnumeratedValue {
+            name: name.to_string(),
+            description: None,
+            value: value.to_string(),
+        }
+    }
+
+    fn field(name: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            enumerated_values: vec![value("DIS", "0x0"), value("EN", "0x1")],
+            ..Field::default()
+        }
+    }
+
+    fn gpio(registers: Vec<(&str, Vec<Field>)>) -> Peripheral {
+        Peripheral {
+            name: "GPIO".to_string(),
+            registers: registers
+                .into_iter()
+                .map(|(name, fields)| Register {
+                    name: name.to_string(),
+                    fields,
+                    ..Register::default()
+                })
+                .collect(),
+            ..Peripheral::default()
+        }
+    }
+
+    fn values_derived_from(peripheral: &Peripheral) -> Vec<Option<&str>> {
+        peripheral
+            .registers
+            .iter()
+            .flat_map(|r| &r.fields)
+            .map(|f| f.enumerated_values_derived_from.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn derive_from_first_field() {
+        let mut gpio = gpio(vec![
+            ("CTL", vec![field("EN0"), field("EN1")]),
+            ("CFG", vec![field("EN2")]),
+        ]);
+        derive_enumerated_values(&mut gpio);
+        assert_eq!(
+            values_derived_from(&gpio),
+            [None, Some("CTL.EN0.EN0"), Some("CTL.EN0.EN0")]
+        );
+        let first = &gpio.registers[0].fields[0];
+        assert_eq!(first.enumerated_values_name.as_deref(), Some("EN0"));
+        // The values stay filled in for the other backends
+        assert_eq!(gpio.registers[1].fields[0].enumerated_values.len(), 2);
+    }
+
+    #[test]
+    fn keep_different_values_and_access() {
+        let mut other = field("EN1");
+        other.enumerated_values[1].name = "ON".to_string();
+        let mut read_only = field("EN2");
+        read_only.access = Some(Access::ReadOnly);
+        let mut gpio = gpio(vec![("CTL", vec![field("EN0"), other, read_only])]);
+        derive_enumerated_values(&mut gpio);
+        assert_eq!(values_derived_from(&gpio), [None, None, None]);
+        assert_eq!(gpio.registers[0].fields[0].enumerated_values_name, None);
+    }
+
+    #[test]
+    fn skip_fields_with_write_values() {
+        let mut split = field("EN1");
+        split.write_enumerated_values = vec![value("CLR", "0x0"), value("SET", "0x1")];
+        let mut gpio = gpio(vec![("CTL", vec![field("EN0"), split, field("EN2")])]);
+        derive_enumerated_values(&mut gpio);
+        assert_eq!(
+            values_derived_from(&gpio),
+            [None, None, Some("CTL.EN0.EN0")]
+        );
+    }
+
+    #[test]
+    fn skip_register_arrays() {
+        let mut gpio = gpio(vec![
+            ("DOUT%s", vec![field("EN0")]),
+            ("CTL", vec![field("EN1")]),
+        ]);
+        gpio.registers[0].dim = Some(crate::model::Dim {
+            dim: 2,
+            dim_increment: 4,
+            dim_index: None,
+        });
+        derive_enumerated_values(&mut gpio);
+        // The array field may derive, but is not derived from
+        assert_eq!(values_derived_from(&gpio), [Some("CTL.EN1.EN1"), None]);
+    }
+
+    #[test]
+    fn skip_fields_without_values() {
+        let mut empty = field("EN0");
+        empty.enumerated_values.clear();
+        let mut gpio = gpio(vec![("CTL", vec![empty.clone(), empty])]);
+        assert_eq!(derive_field_enumerated_values(&mut gpio, 0, 1), None);
+        assert_eq!(values_derived_from(&gpio), [None, None]);
+    }
+}
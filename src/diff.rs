@@ -0,0 +1,421 @@
+//! Comparing two versions of a device, or of a Modules directory, by
+//! peripherals, registers, fields and enumerated values rather than by
+//! XML text.
+//!
+//! Items are matched by name; if a name is used more than once, the
+//! first unmatched item of that name is taken. Descriptions are not
+//! compared.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use xml::reader::EventReader;
+
+use crate::error::{Error, Result};
+use crate::model::{
+    Access, Device, Dim, EnumeratedValue, Field, ModifiedWriteValues, Peripheral, ReadAction,
+    Register,
+};
+use crate::reader::{open_tixml_file, read_peripheral, same_value};
+use crate::Args;
+
+/// What happened to an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One difference between two versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    /// The item, such as `UART0.CTL.MODE`
+    pub path: String,
+    /// Absolute address of the peripheral or register, if known
+    pub address: Option<u64>,
+    /// What changed, such as `reset value 0x0 -> 0x1`
+    pub detail: String,
+}
+
+/// Pair the items of two lists by name.
+fn pair_by_name<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    name: impl Fn(&T) -> &str,
+) -> (Vec<&'a T>, Vec<&'a T>, Vec<(&'a T, &'a T)>) {
+    let mut matched = vec![false; new.len()];
+    let mut removed = vec![];
+    let mut pairs = vec![];
+    for o in old {
+        let found = new
+            .iter()
+            .enumerate()
+            .position(|(i, n)| !matched[i] && name(n) == name(o));
+        match found {
+            Some(i) => {
+                matched[i] = true;
+                pairs.push((o, &new[i]));
+            }
+            None => removed.push(o),
+        }
+    }
+    let added = new
+        .iter()
+        .zip(matched)
+        .filter(|(_, m)| !m)
+        .map(|(n, _)| n)
+        .collect();
+    (added, removed, pairs)
+}
+
+fn hex(value: u64) -> String {
+    format!("0x{:X}", value)
+}
+
+fn opt_hex(value: Option<u64>) -> String {
+    value.map_or_else(|| "none".to_string(), hex)
+}
+
+fn opt<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "none".to_string(), |v| v.to_string())
+}
+
+fn access(access: Option<Access>) -> String {
+    access.map_or("default", Access::as_str).to_string()
+}
+
+fn modified_write_values(modified_write_values: Option<ModifiedWriteValues>) -> String {
+    modified_write_values
+        .map_or("none", ModifiedWriteValues::as_str)
+        .to_string()
+}
+
+fn read_action(read_action: Option<ReadAction>) -> String {
+    read_action.map_or("none", ReadAction::as_str).to_string()
+}
+
+fn bit_range(field: &Field) -> String {
+    match (field.bit_offset, field.bit_width) {
+        (Some(offset), Some(1)) => offset.to_string(),
+        (Some(offset), Some(width)) if width > 0 => {
+            format!("{}:{}", u64::from(offset) + u64::from(width) - 1, offset)
+        }
+        _ => "none".to_string(),
+    }
+}
+
+fn bits(field: &Field) -> String {
+    format!("bits {}", bit_range(field))
+}
+
+fn dim(dim: &Option<Dim>) -> String {
+    match dim {
+        Some(dim) => format!("{} x {}", dim.dim, hex(dim.dim_increment)),
+        None => "none".to_string(),
+    }
+}
+
+struct Differ {
+    entries: Vec<DiffEntry>,
+}
+
+impl Differ {
+    fn push(&mut self, kind: DiffKind, path: &str, address: Option<u64>, detail: String) {
+        self.entries.push(DiffEntry {
+            kind,
+            path: path.to_string(),
+            address,
+            detail,
+        });
+    }
+
+    fn changed<T: PartialEq>(
+        &mut self,
+        path: &str,
+        address: Option<u64>,
+        what: &str,
+        old: T,
+        new: T,
+        show: impl Fn(T) -> String,
+    ) {
+        if old != new {
+            let detail = format!("{} {} -> {}", what, show(old), show(new));
+            self.push(DiffKind::Changed, path, address, detail);
+        }
+    }
+
+    fn enumerated_values(
+        &mut self,
+        path: &str,
+        address: Option<u64>,
+        old: &[EnumeratedValue],
+        new: &[EnumeratedValue],
+    ) {
+        let (added, removed, pairs) = pair_by_name(old, new, |v| &v.name);
+        for value in removed {
+            let path = format!("{}.{}", path, value.name);
+            self.push(
+                DiffKind::Removed,
+                &path,
+                address,
+                format!("value {}", value.value),
+            );
+        }
+        for value in added {
+            let path = format!("{}.{}", path, value.name);
+            self.push(
+                DiffKind::Added,
+                &path,
+                address,
+                format!("value {}", value.value),
+            );
+        }
+        for (o, n) in pairs {
+            if !same_value(&o.value, &n.value) {
+                let path = format!("{}.{}", path, o.name);
+                let detail = format!("value {} -> {}", o.value, n.value);
+                self.push(DiffKind::Changed, &path, address, detail);
+            }
+        }
+    }
+
+    fn fields(&mut self, path: &str, address: Option<u64>, old: &[Field], new: &[Field]) {
+        let (added, removed, pairs) = pair_by_name(old, new, |f| &f.name);
+        for field in removed {
+            let path = format!("{}.{}", path, field.name);
+            self.push(DiffKind::Removed, &path, address, bits(field));
+        }
+        for field in added {
+            let path = format!("{}.{}", path, field.name);
+            self.push(DiffKind::Added, &path, address, bits(field));
+        }
+        for (o, n) in pairs {
+            let path = format!("{}.{}", path, o.name);
+            self.changed(&path, address, "bits", bit_range(o), bit_range(n), |b| b);
+            self.changed(&path, address, "access", o.access, n.access, access);
+            self.changed(
+                &path,
+                address,
+                "write side effect",
+                o.modified_write_values,
+                n.modified_write_values,
+                modified_write_values,
+            );
+            self.changed(
+                &path,
+                address,
+                "read side effect",
+                o.read_action,
+                n.read_action,
+                read_action,
+            );
+            // Values for writing that differ from those for reading count too
+            let old_values: Vec<EnumeratedValue> = o.all_enumerated_values().cloned().collect();
+            let new_values: Vec<EnumeratedValue> = n.all_enumerated_values().cloned().collect();
+            self.enumerated_values(&path, address, &old_values, &new_values);
+        }
+    }
+
+    fn registers(&mut self, path: &str, base: Option<u64>, old: &[Register], new: &[Register]) {
+        let address = |r: &Register| base.map(|b| b + r.address_offset);
+        let (added, removed, pairs) = pair_by_name(old, new, |r| &r.name);
+        for register in removed {
+            let path = format!("{}.{}", path, register.name);
+            let detail = format!("offset {}", hex(register.address_offset));
+            self.push(DiffKind::Removed, &path, address(register), detail);
+        }
+        for register in added {
+            let path = format!("{}.{}", path, register.name);
+            let detail = format!("offset {}", hex(register.address_offset));
+            self.push(DiffKind::Added, &path, address(register), detail);
+        }
+        for (o, n) in pairs {
+            let path = format!("{}.{}", path, o.name);
+            let at = address(n);
+            self.changed(&path, at, "offset", o.address_offset, n.address_offset, hex);
+            self.changed(&path, at, "size", o.size, n.size, opt);
+            self.changed(&path, at, "access", o.access, n.access, access);
+            self.changed(
+                &path,
+                at,
+                "write side effect",
+                o.modified_write_values,
+                n.modified_write_values,
+                modified_write_values,
+            );
+            self.changed(
+                &path,
+                at,
+                "read side effect",
+                o.read_action,
+                n.read_action,
+                read_action,
+            );
+            self.changed(
+                &path,
+                at,
+                "reset value",
+                o.reset_value,
+                n.reset_value,
+                opt_hex,
+            );
+            self.changed(&path, at, "array", &o.dim, &n.dim, dim);
+            self.fields(&path, at, &o.fields, &n.fields);
+        }
+    }
+
+    fn peripheral(&mut self, path: &str, old: &Peripheral, new: &Peripheral) {
+        let at = new.base_address;
+        self.changed(
+            path,
+            at,
+            "base address",
+            old.base_address,
+            new.base_address,
+            opt_hex,
+        );
+        self.changed(path, at, "size", old.size, new.size, opt_hex);
+        self.changed(
+            path,
+            at,
+            "derived from",
+            old.derived_from.as_deref(),
+            new.derived_from.as_deref(),
+            |d| d.unwrap_or("none").to_string(),
+        );
+        self.registers(path, at, &old.registers, &new.registers);
+    }
+}
+
+/// Compare two versions of a device.
+pub fn diff_devices(old: &Device, new: &Device) -> Vec<DiffEntry> {
+    let mut differ = Differ { entries: vec![] };
+    let (added, removed, pairs) = pair_by_name(&old.peripherals, &new.peripherals, |p| &p.name);
+    for peripheral in removed {
+        let detail = format!("{} registers", peripheral.registers.len());
+        differ.push(
+            DiffKind::Removed,
+            &peripheral.name,
+            peripheral.base_address,
+            detail,
+        );
+    }
+    for peripheral in added {
+        let detail = format!("{} registers", peripheral.registers.len());
+        differ.push(
+            DiffKind::Added,
+            &peripheral.name,
+            peripheral.base_address,
+            detail,
+        );
+    }
+    for (o, n) in pairs {
+        differ.peripheral(&o.name, o, n);
+    }
+    differ.entries
+}
+
+/// The TIXML files below a directory, relative to it and sorted.
+fn module_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        let entries = fs::read_dir(&current).map_err(|e| Error::from(e).in_file(&current))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "xml") {
+                files.push(path.strip_prefix(dir).unwrap_or(&path).to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn load_module(args: &Args, path: &Path) -> Result<Peripheral> {
+    let parser = EventReader::new(open_tixml_file(path)?);
+    let warnings = args.warning_count();
+    let result = read_peripheral(args, parser);
+    args.locate_warnings(warnings, |w| w.in_file(path));
+    result.map_err(|e| e.in_file(path))
+}
+
+/// Compare two versions of a Modules directory, module file by module
+/// file. Registers have no address, as modules have no base address.
+/// Files that cannot be read are skipped with a warning.
+pub fn diff_module_dirs(args: &Args, old: &Path, new: &Path) -> Result<Vec<DiffEntry>> {
+    let old_files = module_files(old)?;
+    let new_files = module_files(new)?;
+    let name = |path: &PathBuf| path.with_extension("").display().to_string();
+    let (added, removed, pairs) =
+        pair_by_name(&old_files, &new_files, |p| p.to_str().unwrap_or(""));
+
+    let mut differ = Differ { entries: vec![] };
+    for file in removed {
+        differ.push(
+            DiffKind::Removed,
+            &name(file),
+            None,
+            "module file".to_string(),
+        );
+    }
+    for file in added {
+        differ.push(
+            DiffKind::Added,
+            &name(file),
+            None,
+            "module file".to_string(),
+        );
+    }
+    for (file, _) in pairs {
+        if args.verbose > 0 {
+            eprintln!("Comparing module {}", file.display());
+        }
+        match (
+            load_module(args, &old.join(file)),
+            load_module(args, &new.join(file)),
+        ) {
+            (Ok(old_peripheral), Ok(new_peripheral)) => {
+                differ.peripheral(&name(file), &old_peripheral, &new_peripheral)
+            }
+            // Modules directories also hold files that are not modules
+            (Err(e), _) | (_, Err(e)) => args.warn(e),
+        }
+    }
+    Ok(differ.entries)
+}
+
+/// Print the differences, one per line, marked `+` if added, `-` if
+/// removed and `~` if changed.
+pub fn write_diff<O>(entries: &[DiffEntry], out: &mut O) -> io::Result<()>
+where
+    O: io::Write,
+{
+    let width = entries.iter().map(|e| e.path.len()).max().unwrap_or(0);
+    for entry in entries {
+        let mark = match entry.kind {
+            DiffKind::Added => '+',
+            DiffKind::Removed => '-',
+            DiffKind::Changed => '~',
+        };
+        let address = entry
+            .address
+            .map_or_else(String::new, |a| format!("0x{:08X}", a));
+        writeln!(
+            out,
+            "{} {:<width$}  {:<10}  {}",
+            mark,
+            entry.path,
+            address,
+            entry.detail,
+            width = width
+        )?;
+    }
+    Ok(())
+}
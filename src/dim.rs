@@ -0,0 +1,257 @@
+//! Combining repeated registers into SVD arrays.
+//!
+//! TI module files often list registers such as `DOUT0`, `DOUT1`, ...
+//! one by one. Runs of registers whose names only differ in a number,
+//! which are laid out with a constant stride and have identical fields,
+//! are replaced by a single register with `<dim>` information.
+
+use crate::model::{Dim, Peripheral, Register};
+
+/// Split a name at its last run of digits into prefix, index and suffix.
+fn split_index(name: &str) -> Option<(&str, &str, &str)> {
+    let end = name.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = name[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    Some((&name[..start], &name[start..end], &name[end..]))
+}
+
+/// Whether two registers differ in no more than name, description and
+/// address.
+fn same_layout(a: &Register, b: &Register) -> bool {
+    a.dim.is_none()
+        && b.dim.is_none()
+        && a.value == b.value
+        && a.size == b.size
+        && a.access == b.access
+        && a.modified_write_values == b.modified_write_values
+        && a.read_action == b.read_action
+        && a.reset_value == b.reset_value
+        && a.fields == b.fields
+}
+
+/// The number of registers at the start of `registers` that can be
+/// combined into one array.
+fn run_length(registers: &[Register]) -> usize {
+    let first = &registers[0];
+    let (prefix, index, suffix) = match split_index(&first.name) {
+        Some(split) => split,
+        None => return 1,
+    };
+    let first_index: u64 = match index.parse() {
+        Ok(index) => index,
+        Err(_) => return 1,
+    };
+
+    let mut stride = None;
+    let mut len = 1;
+    for (previous, register) in registers.iter().zip(&registers[1..]) {
+        let follows = match split_index(&register.name) {
+            Some((p, i, s)) => {
+                p == prefix && s == suffix && i.parse() == Ok(first_index + len as u64)
+            }
+            None => false,
+        };
+        let step = register
+            .address_offset
+            .checked_sub(previous.address_offset)
+            .filter(|&step| step > 0);
+        if !follows || step.is_none() || (stride.is_some() && step != stride) {
+            break;
+        }
+        if !same_layout(first, register) {
+            break;
+        }
+        stride = step;
+        len += 1;
+    }
+    len
+}
+
+/// Replace the last occurrence of `index` in `description` by `%s`.
+fn generic_description(description: &str, index: &str) -> String {
+    match description.rfind(index) {
+        Some(i) => format!("{}%s{}", &description[..i], &description[i + index.len()..]),
+        None => description.to_string(),
+    }
+}
+
+/// Combine a run of registers, as found by `run_length`.
+fn make_array(registers: &[Register]) -> Register {
+    let first = &registers[0];
+    let (prefix, _, suffix) = split_index(&first.name).unwrap_or_default();
+    let indices: Vec<&str> = registers
+        .iter()
+        .filter_map(|r| split_index(&r.name).map(|(_, index, _)| index))
+        .collect();
+
+    let descriptions: Vec<Option<String>> = registers
+        .iter()
+        .zip(&indices)
+        .map(|(r, index)| {
+            r.description
+                .as_ref()
+                .map(|d| generic_description(d, index))
+        })
+        .collect();
+    let description = if descriptions.iter().all(|d| *d == descriptions[0]) {
+        descriptions[0].clone()
+    } else {
+        first.description.clone()
+    };
+
+    // Use the short range form, unless indices have leading zeros
+    let first_index = indices[0];
+    let last_index = indices[indices.len() - 1];
+    let plain = indices.iter().all(|i| i.len() == 1 || !i.starts_with('0'));
+    let dim_index = if plain {
+        format!("{}-{}", first_index, last_index)
+    } else {
+        indices.join(",")
+    };
+
+    Register {
+        dim: Some(Dim {
+            dim: registers.len() as u32,
+            dim_increment: registers[1].address_offset - first.address_offset,
+            dim_index: Some(dim_index),
+        }),
+        name: format!("{}%s{}", prefix, suffix),
+        description,
+        ..first.clone()
+    }
+}
+
+/// Replace runs of repeated registers by register arrays.
+pub fn make_register_arrays(peripheral: &mut Peripheral) {
+    let registers = std::mem::take(&mut peripheral.registers);
+    let mut i = 0;
+    while i < registers.len() {
+        let len = run_length(&registers[i..]);
+        if len > 1 {
+            peripheral
+                .registers
+                .push(make_array(&registers[i..i + len]));
+        } else {
+            peripheral.registers.push(registers[i].clone());
+        }
+        i += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Field;
+
+    fn register(name: &str, address_offset: u64) -> Register {
+        Register {
+            name: name.to_string(),
+            description: Some(format!("Register {}", name)),
+            address_offset,
+            fields: vec![Field {
+                name: "VALUE".to_string(),
+                bit_offset: Some(0),
+                bit_width: Some(8),
+                ..Field::default()
+            }],
+            ..Register::default()
+        }
+    }
+
+    fn arrays(registers: Vec<Register>) -> Vec<Register> {
+        let mut peripheral = Peripheral {
+            registers,
+            ..Peripheral::default()
+        };
+        make_register_arrays(&mut peripheral);
+        peripheral.registers
+    }
+
+    #[test]
+    fn split_names() {
+        assert_eq!(split_index("DOUT12"), Some(("DOUT", "12", "")));
+        assert_eq!(split_index("CH3_CTL"), Some(("CH", "3", "_CTL")));
+        assert_eq!(split_index("CTL"), None);
+    }
+
+    #[test]
+    fn combine_run() {
+        let registers = arrays(vec![
+            register("DOUT0", 0x10),
+            register("DOUT1", 0x14),
+            register("DOUT2", 0x18),
+        ]);
+        assert_eq!(registers.len(), 1);
+        let array = &registers[0];
+        assert_eq!(array.name, "DOUT%s");
+        assert_eq!(array.address_offset, 0x10);
+        assert_eq!(array.description.as_deref(), Some("Register DOUT%s"));
+        assert_eq!(
+            array.dim,
+            Some(Dim {
+                dim: 3,
+                dim_increment: 4,
+                dim_index: Some("0-2".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn combine_with_suffix() {
+        let registers = arrays(vec![register("CH0_CTL", 0), register("CH1_CTL", 8)]);
+        assert_eq!(registers.len(), 1);
+        assert_eq!(registers[0].name, "CH%s_CTL");
+    }
+
+    #[test]
+    fn leading_zeros_give_index_list() {
+        let registers = arrays(vec![
+            register("REG08", 0),
+            register("REG09", 4),
+            register("REG10", 8),
+        ]);
+        assert_eq!(registers.len(), 1);
+        let dim = registers[0].dim.as_ref().unwrap();
+        assert_eq!(dim.dim_index.as_deref(), Some("08,09,10"));
+        assert_eq!(dim.indices(), ["08", "09", "10"]);
+    }
+
+    #[test]
+    fn stop_at_stride_change() {
+        let registers = arrays(vec![
+            register("DOUT0", 0),
+            register("DOUT1", 4),
+            register("DOUT2", 12),
+        ]);
+        let names: Vec<&str> = registers.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["DOUT%s", "DOUT2"]);
+        assert_eq!(registers[0].dim.as_ref().unwrap().dim, 2);
+    }
+
+    #[test]
+    fn keep_registers_that_differ() {
+        let mut other = register("DOUT1", 4);
+        other.fields[0].bit_width = Some(4);
+        let registers = arrays(vec![register("DOUT0", 0), other]);
+        assert_eq!(registers.len(), 2);
+        assert!(registers.iter().all(|r| r.dim.is_none()));
+    }
+
+    #[test]
+    fn keep_gaps_in_indices() {
+        let registers = arrays(vec![register("DOUT0", 0), register("DOUT2", 4)]);
+        assert_eq!(registers.len(), 2);
+    }
+
+    #[test]
+    fn keep_mixed_descriptions() {
+        let mut first = register("DOUT0", 0);
+        first.description = Some("First data output".to_string());
+        let registers = arrays(vec![first, register("DOUT1", 4)]);
+        assert_eq!(
+            registers[0].description.as_deref(),
+            Some("First data output")
+        );
+    }
+}
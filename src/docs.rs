@@ -0,0 +1,526 @@
+//! Writing the device model as register reference pages, in Markdown or
+//! HTML.
+//!
+//! There is an index page listing the peripherals, and a page per
+//! peripheral with a register table, and for each register a bit
+//! diagram and tables of its fields and enumerated values. Derived
+//! peripherals link to the page of the original. The `#br#` markers in
+//! TIXML descriptions become line breaks.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result, Warning};
+use crate::model::{
+    Access, Device, EnumeratedValue, Field, ModifiedWriteValues, Peripheral, ReadAction, Register,
+};
+use crate::reader::parse_number;
+use crate::Args;
+
+/// The format of the pages written by [`write_docs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl DocFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            DocFormat::Markdown => "md",
+            DocFormat::Html => "html",
+        }
+    }
+
+    /// Text from a TIXML description. Markdown tables cannot hold a
+    /// line break, so there it is an HTML `<br>`.
+    fn text(self, text: &str, in_table: bool) -> String {
+        let lines: Vec<String> = text
+            .split("#br#")
+            .map(|line| {
+                let line = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+                let line = line
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                match self {
+                    DocFormat::Markdown if in_table => line.replace('|', "\\|"),
+                    _ => line,
+                }
+            })
+            .filter(|line| !line.is_empty())
+            .collect();
+        match (self, in_table) {
+            (DocFormat::Markdown, false) => lines.join("  \n"),
+            _ => lines.join("<br>"),
+        }
+    }
+
+    fn code(self, text: &str) -> String {
+        match self {
+            DocFormat::Markdown => format!("`{}`", text),
+            DocFormat::Html => format!("<code>{}</code>", self.text(text, true)),
+        }
+    }
+
+    fn link(self, text: &str, href: &str) -> String {
+        match self {
+            DocFormat::Markdown => format!("[{}]({})", text, href),
+            DocFormat::Html => format!("<a href=\"{}\">{}</a>", href, text),
+        }
+    }
+
+    fn begin(self, out: &mut String, title: &str) -> std::fmt::Result {
+        if self == DocFormat::Html {
+            writeln!(out, "<!DOCTYPE html>")?;
+            writeln!(out, "<html>")?;
+            writeln!(out, "<head>")?;
+            writeln!(out, "<meta charset=\"utf-8\">")?;
+            writeln!(out, "<title>{}</title>", self.text(title, true))?;
+            writeln!(out, "<style>")?;
+            writeln!(
+                out,
+                "table {{ border-collapse: collapse; }} \
+                 th, td {{ border: 1px solid #999; padding: 2px 6px; }} \
+                 table.bits td {{ text-align: center; font-family: monospace; }}"
+            )?;
+            writeln!(out, "</style>")?;
+            writeln!(out, "</head>")?;
+            writeln!(out, "<body>")?;
+        }
+        Ok(())
+    }
+
+    fn end(self, out: &mut String) -> std::fmt::Result {
+        if self == DocFormat::Html {
+            writeln!(out, "</body>")?;
+            writeln!(out, "</html>")?;
+        }
+        Ok(())
+    }
+
+    fn heading(self, out: &mut String, level: usize, text: &str) -> std::fmt::Result {
+        match self {
+            DocFormat::Markdown => writeln!(out, "{} {}\n", "#".repeat(level), text),
+            DocFormat::Html => writeln!(
+                out,
+                "<h{} id=\"{}\">{}</h{}>",
+                level,
+                anchor(text),
+                text,
+                level
+            ),
+        }
+    }
+
+    fn paragraph(self, out: &mut String, text: &str) -> std::fmt::Result {
+        if text.is_empty() {
+            return Ok(());
+        }
+        match self {
+            DocFormat::Markdown => writeln!(out, "{}\n", text),
+            DocFormat::Html => writeln!(out, "<p>{}</p>", text),
+        }
+    }
+
+    fn table(self, out: &mut String, headers: &[&str], rows: &[Vec<String>]) -> std::fmt::Result {
+        match self {
+            DocFormat::Markdown => {
+                writeln!(out, "| {} |", headers.join(" | "))?;
+                writeln!(out, "|{}", "---|".repeat(headers.len()))?;
+                for row in rows {
+                    writeln!(out, "| {} |", row.join(" | "))?;
+                }
+                writeln!(out)
+            }
+            DocFormat::Html => {
+                writeln!(out, "<table>")?;
+                writeln!(out, "<tr><th>{}</th></tr>", headers.join("</th><th>"))?;
+                for row in rows {
+                    writeln!(out, "<tr><td>{}</td></tr>", row.join("</td><td>"))?;
+                }
+                writeln!(out, "</table>")
+            }
+        }
+    }
+
+    /// A diagram of the fields of a register, from the most significant
+    /// bit down. Unused bits are left blank.
+    fn bit_diagram(
+        self,
+        out: &mut String,
+        register_width: u32,
+        fields: &[Field],
+    ) -> std::fmt::Result {
+        let mut spans: Vec<(u32, u32, &str)> = vec![];
+        for field in fields {
+            if let (Some(offset), Some(width)) = (field.bit_offset, field.bit_width) {
+                let (low, high) = (offset, offset + width);
+                if width > 0
+                    && high <= register_width
+                    && spans.iter().all(|s| high <= s.0 || s.1 <= low)
+                {
+                    spans.push((low, high, &field.name));
+                }
+            }
+        }
+        spans.sort_by_key(|s| std::cmp::Reverse(s.0));
+
+        let mut cells = vec![];
+        let mut next = register_width;
+        for (low, high, name) in spans.into_iter().chain(Some((0, 0, ""))) {
+            if high < next {
+                cells.push((high, next - 1, ""));
+            }
+            if high > low {
+                cells.push((low, high - 1, name));
+            }
+            next = low;
+        }
+
+        match self {
+            DocFormat::Markdown => {
+                let mut bits = String::from("|");
+                let mut names = String::from("|");
+                for (low, high, name) in cells {
+                    let label = if low == high {
+                        low.to_string()
+                    } else {
+                        format!("{}:{}", high, low)
+                    };
+                    let width = label.len().max(name.len()) + 1;
+                    write!(bits, "{:<width$}|", label, width = width)?;
+                    write!(names, "{:<width$}|", name, width = width)?;
+                }
+                writeln!(out, "```text\n{}\n{}\n```\n", bits, names)
+            }
+            DocFormat::Html => {
+                writeln!(out, "<table class=\"bits\">")?;
+                write!(out, "<tr>")?;
+                for bit in (0..register_width).rev() {
+                    write!(out, "<td>{}</td>", bit)?;
+                }
+                writeln!(out, "</tr>")?;
+                write!(out, "<tr>")?;
+                for (low, high, name) in cells {
+                    write!(out, "<td colspan=\"{}\">{}</td>", high - low + 1, name)?;
+                }
+                writeln!(out, "</tr>")?;
+                writeln!(out, "</table>")
+            }
+        }
+    }
+}
+
+/// The id of a heading, as GitHub makes it for Markdown.
+fn anchor(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect()
+}
+
+/// The file name of the page of a peripheral, without extension.
+fn page_name(peripheral: &Peripheral) -> String {
+    peripheral
+        .name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn hex(value: u64, bits: u32) -> String {
+    format!(
+        "0x{:0width$X}",
+        value,
+        width = bits.div_ceil(4).max(1) as usize
+    )
+}
+
+/// An access type, followed by the side effects of writing and reading.
+fn access_text(
+    access: Access,
+    modified_write_values: Option<ModifiedWriteValues>,
+    read_action: Option<ReadAction>,
+) -> String {
+    let mut text = access.as_str().to_string();
+    if let Some(modified_write_values) = modified_write_values {
+        text = format!("{}, {}", text, modified_write_values.as_str());
+    }
+    if let Some(read_action) = read_action {
+        text = format!("{}, read: {}", text, read_action.as_str());
+    }
+    text
+}
+
+fn register_access(device: &Device, register: &Register) -> String {
+    access_text(
+        register.access.unwrap_or(device.access),
+        register.modified_write_values,
+        register.read_action,
+    )
+}
+
+/// The bits of a field, such as `7:4`, or `-` if its offset or width is
+/// not known.
+fn bit_range(offset: Option<u32>, width: Option<u32>) -> String {
+    match (offset, width) {
+        (Some(offset), Some(1)) => offset.to_string(),
+        (Some(offset), Some(width)) if width > 0 => {
+            format!("{}:{}", u64::from(offset) + u64::from(width) - 1, offset)
+        }
+        _ => "-".to_string(),
+    }
+}
+
+fn write_register(
+    args: &Args,
+    format: DocFormat,
+    device: &Device,
+    register: &Register,
+    out: &mut String,
+) -> std::fmt::Result {
+    let register_width = register.size.unwrap_or(device.size);
+    let reset_value = register.reset_value.unwrap_or(device.reset_value);
+
+    format.heading(out, 2, &register.name)?;
+    format.paragraph(
+        out,
+        &format.text(register.description.as_deref().unwrap_or(""), false),
+    )?;
+    let mut summary = format!(
+        "Offset {}, {} bits, {}, reset value {}.",
+        format.code(&hex(register.address_offset, 12)),
+        register_width,
+        register_access(device, register),
+        format.code(&hex(reset_value, register_width)),
+    );
+    if let Some(ref dim) = register.dim {
+        write!(
+            summary,
+            " Array of {} registers {} apart, with {} standing for {}.",
+            dim.dim,
+            format.code(&hex(dim.dim_increment, 4)),
+            format.code("%s"),
+            dim.indices().join(", "),
+        )?;
+    }
+    format.paragraph(out, &summary)?;
+    format.bit_diagram(out, register_width, &register.fields)?;
+
+    let mut fields: Vec<&Field> = register.fields.iter().collect();
+    fields.sort_by_key(|f| std::cmp::Reverse(f.bit_offset));
+    let rows: Vec<Vec<String>> = fields
+        .iter()
+        .map(|field| {
+            let (offset, width) = (field.bit_offset.unwrap_or(0), field.bit_width.unwrap_or(0));
+            let field_reset = if width == 0 || offset >= 64 {
+                0
+            } else {
+                (reset_value >> offset) & (u64::MAX >> (64 - width.min(64)))
+            };
+            vec![
+                bit_range(field.bit_offset, field.bit_width),
+                field.name.clone(),
+                match field.access {
+                    Some(access) => {
+                        access_text(access, field.modified_write_values, field.read_action)
+                    }
+                    None => register_access(device, register),
+                },
+                format.code(&hex(field_reset, width)),
+                format.text(field.description.as_deref().unwrap_or(""), true),
+            ]
+        })
+        .collect();
+    if !rows.is_empty() {
+        format.table(
+            out,
+            &["Bits", "Field", "Access", "Reset", "Description"],
+            &rows,
+        )?;
+    }
+
+    for field in fields.iter().filter(|f| !f.enumerated_values.is_empty()) {
+        let heading = format!("{}.{}", register.name, field.name);
+        if field.write_enumerated_values.is_empty() {
+            format.heading(out, 3, &heading)?;
+            write_enum_table(args, format, register, field, &field.enumerated_values, out)?;
+        } else {
+            format.heading(out, 3, &format!("{} (read)", heading))?;
+            write_enum_table(args, format, register, field, &field.enumerated_values, out)?;
+            format.heading(out, 3, &format!("{} (write)", heading))?;
+            write_enum_table(
+                args,
+                format,
+                register,
+                field,
+                &field.write_enumerated_values,
+                out,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_enum_table(
+    args: &Args,
+    format: DocFormat,
+    register: &Register,
+    field: &Field,
+    values: &[EnumeratedValue],
+    out: &mut String,
+) -> std::fmt::Result {
+    let rows: Vec<Vec<String>> = values
+        .iter()
+        .map(|value| {
+            let number = match parse_number("value", &value.value) {
+                Ok(number) => hex(number, field.bit_width.unwrap_or(1)),
+                Err(e) => {
+                    args.warn(e.in_field(&field.name).in_register(&register.name));
+                    value.value.clone()
+                }
+            };
+            vec![
+                format.code(&number),
+                value.name.clone(),
+                format.text(value.description.as_deref().unwrap_or(""), true),
+            ]
+        })
+        .collect();
+    format.table(out, &["Value", "Name", "Description"], &rows)
+}
+
+fn write_peripheral_page(
+    args: &Args,
+    format: DocFormat,
+    device: &Device,
+    peripheral: &Peripheral,
+) -> Result<String> {
+    let mut out = String::new();
+    let title = format!("{} {}", device.name, peripheral.name);
+    format.begin(&mut out, &title)?;
+    format.heading(&mut out, 1, &peripheral.name)?;
+    format.paragraph(
+        &mut out,
+        &format.text(peripheral.description.as_deref().unwrap_or(""), false),
+    )?;
+
+    let instances: Vec<String> = device
+        .peripherals
+        .iter()
+        .filter(|p| {
+            p.name == peripheral.name || p.derived_from.as_deref() == Some(&peripheral.name)
+        })
+        .filter_map(|p| {
+            p.base_address
+                .map(|base| format!("{} at {}", p.name, format.code(&hex(base, device.width))))
+        })
+        .collect();
+    if !instances.is_empty() {
+        format.paragraph(&mut out, &format!("Instances: {}.", instances.join(", ")))?;
+    }
+
+    let mut registers: Vec<&Register> = peripheral.registers.iter().collect();
+    registers.sort_by_key(|r| r.address_offset);
+    let rows: Vec<Vec<String>> = registers
+        .iter()
+        .map(|register| {
+            let width = register.size.unwrap_or(device.size);
+            vec![
+                format.code(&hex(register.address_offset, 12)),
+                format.link(&register.name, &format!("#{}", anchor(&register.name))),
+                register_access(device, register),
+                format.code(&hex(
+                    register.reset_value.unwrap_or(device.reset_value),
+                    width,
+                )),
+                format.text(register.description.as_deref().unwrap_or(""), true),
+            ]
+        })
+        .collect();
+    format.table(
+        &mut out,
+        &["Offset", "Register", "Access", "Reset", "Description"],
+        &rows,
+    )?;
+
+    for register in registers {
+        write_register(args, format, device, register, &mut out)?;
+    }
+    format.end(&mut out)?;
+    Ok(out)
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents).map_err(|e| Error::from(e).in_file(path))
+}
+
+/// Write the register reference of a device into `dir`, which is created
+/// if needed: an index page, and a page per peripheral that is not
+/// derived from another one.
+pub fn write_docs(args: &Args, device: &Device, dir: &Path, format: DocFormat) -> Result<()> {
+    fs::create_dir_all(dir).map_err(|e| Error::from(e).in_file(dir))?;
+    let extension = format.extension();
+
+    let mut pages = HashSet::new();
+    let mut rows = vec![];
+    for peripheral in &device.peripherals {
+        let page = match peripheral.derived_from {
+            Some(ref original) => device
+                .peripherals
+                .iter()
+                .find(|p| &p.name == original)
+                .map(page_name),
+            None if !pages.insert(page_name(peripheral)) => {
+                args.warn(
+                    Warning::DuplicateName {
+                        context: Box::default(),
+                        name: page_name(peripheral),
+                    }
+                    .in_peripheral(&peripheral.name),
+                );
+                None
+            }
+            None => {
+                if args.verbose > 0 {
+                    eprintln!("Writing documentation for peripheral {}", peripheral.name);
+                }
+                let warnings = args.warning_count();
+                let text = write_peripheral_page(args, format, device, peripheral)?;
+                args.locate_warnings(warnings, |w| w.in_peripheral(&peripheral.name));
+                let page = page_name(peripheral);
+                write_file(&dir.join(format!("{}.{}", page, extension)), &text)?;
+                Some(page)
+            }
+        };
+        let name = match page {
+            Some(page) => format.link(&peripheral.name, &format!("{}.{}", page, extension)),
+            None => peripheral.name.clone(),
+        };
+        rows.push(vec![
+            name,
+            peripheral
+                .base_address
+                .map(|base| format.code(&hex(base, device.width)))
+                .unwrap_or_default(),
+            peripheral.group_name.clone().unwrap_or_default(),
+            format.text(peripheral.description.as_deref().unwrap_or(""), true),
+        ]);
+    }
+
+    let mut index = String::new();
+    format.begin(&mut index, &device.name)?;
+    format.heading(&mut index, 1, &device.name)?;
+    format.paragraph(&mut index, &format.text(&device.description, false))?;
+    format.table(
+        &mut index,
+        &["Peripheral", "Base address", "Group", "Description"],
+        &rows,
+    )?;
+    format.end(&mut index)?;
+    write_file(&dir.join(format!("index.{}", extension)), &index)
+}
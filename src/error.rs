@@ -0,0 +1,493 @@
+//! Errors reported by tixml2svd.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Where an error happened. All parts are optional, and filled in as
+/// the error is passed up from a field to its register, peripheral and
+/// file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Context {
+    pub file: Option<PathBuf>,
+    /// Line in `file`, starting at 1
+    pub line: Option<u64>,
+    /// Column in `file`, starting at 1
+    pub column: Option<u64>,
+    pub peripheral: Option<String>,
+    pub register: Option<String>,
+    pub field: Option<String>,
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}", file.display())?;
+            if let (Some(line), Some(column)) = (self.line, self.column) {
+                write!(f, ":{}:{}", line, column)?;
+            }
+            write!(f, ": ")?;
+        }
+
+        let names: Vec<String> = [
+            ("peripheral", &self.peripheral),
+            ("register", &self.register),
+            ("field", &self.field),
+        ]
+        .iter()
+        .filter_map(|(what, name)| name.as_ref().map(|n| format!("{} {}", what, n)))
+        .collect();
+        if !names.is_empty() {
+            write!(f, "{}: ", names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Context {
+    fn locate_file(&mut self, file: PathBuf) {
+        if self.file.is_none() {
+            self.file = Some(file);
+        }
+    }
+
+    fn locate_position(&mut self, position: xml::common::TextPosition) {
+        if self.line.is_none() && self.file.is_none() {
+            self.line = Some(position.row + 1);
+            self.column = Some(position.column + 1);
+        }
+    }
+
+    fn locate_line(&mut self, line: u64) {
+        if self.line.is_none() {
+            self.line = Some(line);
+            self.column = Some(1);
+        }
+    }
+
+    fn locate_name(name: &mut Option<String>, value: &str) {
+        if name.is_none() {
+            *name = Some(value.to_string());
+        }
+    }
+}
+
+/// The ways a conversion can fail.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io {
+        context: Box<Context>,
+        source: io::Error,
+    },
+    /// The input is not well-formed XML.
+    Xml {
+        context: Box<Context>,
+        message: String,
+    },
+    /// The SVD output could not be written.
+    Write {
+        context: Box<Context>,
+        message: String,
+    },
+    /// The input file uses a unicode encoding other than UTF-8.
+    UnsupportedEncoding {
+        context: Box<Context>,
+        encoding: String,
+    },
+    /// An interrupt file or other non-XML input is malformed.
+    Syntax {
+        context: Box<Context>,
+        message: String,
+    },
+    /// An attribute or element has a value that cannot be used.
+    InvalidValue {
+        context: Box<Context>,
+        key: String,
+        value: String,
+    },
+    /// A field does not fit into its register.
+    FieldTooBig {
+        context: Box<Context>,
+        bit_offset: u32,
+        bit_width: u32,
+        register_width: u32,
+    },
+    /// A field reset value does not fit into the field.
+    ResetValueTooBig {
+        context: Box<Context>,
+        reset_value: u64,
+    },
+    /// A required attribute is missing.
+    MissingValue { context: Box<Context>, key: String },
+}
+
+/// Problems that do not stop a conversion, but may change its output.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Warning {
+    /// An error that is worked around, for example by leaving out the
+    /// value concerned.
+    Error(Error),
+    /// A register name is used twice in a peripheral.
+    DuplicateRegister { context: Box<Context>, name: String },
+    /// An enumeration value is used twice in a field.
+    DuplicateEnumValue {
+        context: Box<Context>,
+        value: String,
+    },
+    /// No CPU information is known for the device.
+    UnknownCpu {
+        context: Box<Context>,
+        device: String,
+        isa: String,
+    },
+    /// No peripheral was found for an interrupt, so it is added to
+    /// another one, if the device has any.
+    UnassignedInterrupt {
+        context: Box<Context>,
+        name: String,
+        peripheral: Option<String>,
+    },
+    /// A peripheral, register or field overlaps another one.
+    Overlap {
+        context: Box<Context>,
+        other: String,
+    },
+    /// An enumerated value does not fit into its field.
+    EnumValueTooBig {
+        context: Box<Context>,
+        value: String,
+        bit_width: u32,
+    },
+    /// Two items would get the same name in generated code.
+    DuplicateName { context: Box<Context>, name: String },
+    /// Two peripherals of a device, registers of a peripheral or fields
+    /// of a register have the same name.
+    NameNotUnique { context: Box<Context>, name: String },
+    /// An enumerated value that does not fit into its field is left out.
+    EnumValueDropped {
+        context: Box<Context>,
+        value: String,
+        bit_width: u32,
+    },
+    /// An enumerated value given as register value is shifted into its
+    /// field.
+    EnumValueShifted {
+        context: Box<Context>,
+        value: String,
+        shifted: u64,
+    },
+    /// The enumerated values of a field are written as derived from the
+    /// identical ones of another field.
+    EnumValuesDerived { context: Box<Context>, path: String },
+    /// An enumerated value has another name for writing than for
+    /// reading, so the field gets separate values for both.
+    EnumValueSplit {
+        context: Box<Context>,
+        value: String,
+    },
+}
+
+/// Result type of the tixml2svd functions.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn invalid_value(key: &str, value: &str) -> Error {
+        Error::InvalidValue {
+            context: Box::default(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Where the error happened.
+    pub fn context(&self) -> &Context {
+        match self {
+            Error::Io { context, .. }
+            | Error::Xml { context, .. }
+            | Error::Write { context, .. }
+            | Error::UnsupportedEncoding { context, .. }
+            | Error::Syntax { context, .. }
+            | Error::InvalidValue { context, .. }
+            | Error::FieldTooBig { context, .. }
+            | Error::ResetValueTooBig { context, .. }
+            | Error::MissingValue { context, .. } => context,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        match self {
+            Error::Io { context, .. }
+            | Error::Xml { context, .. }
+            | Error::Write { context, .. }
+            | Error::UnsupportedEncoding { context, .. }
+            | Error::Syntax { context, .. }
+            | Error::InvalidValue { context, .. }
+            | Error::FieldTooBig { context, .. }
+            | Error::ResetValueTooBig { context, .. }
+            | Error::MissingValue { context, .. } => context,
+        }
+    }
+
+    /// Set the file of the error, unless it is already known.
+    pub fn in_file<P: Into<PathBuf>>(mut self, file: P) -> Error {
+        self.context_mut().locate_file(file.into());
+        self
+    }
+
+    /// Set the position of the error in the file being read, unless it
+    /// is already known or the error happened in another file.
+    pub(crate) fn at(mut self, position: xml::common::TextPosition) -> Error {
+        self.context_mut().locate_position(position);
+        self
+    }
+
+    /// Set the line of the error, unless it is already known.
+    pub(crate) fn at_line(mut self, line: u64) -> Error {
+        self.context_mut().locate_line(line);
+        self
+    }
+
+    pub(crate) fn in_peripheral(mut self, name: &str) -> Error {
+        Context::locate_name(&mut self.context_mut().peripheral, name);
+        self
+    }
+
+    pub(crate) fn in_register(mut self, name: &str) -> Error {
+        Context::locate_name(&mut self.context_mut().register, name);
+        self
+    }
+
+    pub(crate) fn in_field(mut self, name: &str) -> Error {
+        Context::locate_name(&mut self.context_mut().field, name);
+        self
+    }
+}
+
+impl Warning {
+    /// Where the problem was found.
+    pub fn context(&self) -> &Context {
+        match self {
+            Warning::Error(error) => error.context(),
+            Warning::DuplicateRegister { context, .. }
+            | Warning::DuplicateEnumValue { context, .. }
+            | Warning::UnknownCpu { context, .. }
+            | Warning::UnassignedInterrupt { context, .. }
+            | Warning::Overlap { context, .. }
+            | Warning::EnumValueTooBig { context, .. }
+            | Warning::DuplicateName { context, .. }
+            | Warning::NameNotUnique { context, .. }
+            | Warning::EnumValueDropped { context, .. }
+            | Warning::EnumValueShifted { context, .. }
+            | Warning::EnumValuesDerived { context, .. }
+            | Warning::EnumValueSplit { context, .. } => context,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        match self {
+            Warning::Error(error) => error.context_mut(),
+            Warning::DuplicateRegister { context, .. }
+            | Warning::DuplicateEnumValue { context, .. }
+            | Warning::UnknownCpu { context, .. }
+            | Warning::UnassignedInterrupt { context, .. }
+            | Warning::Overlap { context, .. }
+            | Warning::EnumValueTooBig { context, .. }
+            | Warning::DuplicateName { context, .. }
+            | Warning::NameNotUnique { context, .. }
+            | Warning::EnumValueDropped { context, .. }
+            | Warning::EnumValueShifted { context, .. }
+            | Warning::EnumValuesDerived { context, .. }
+            | Warning::EnumValueSplit { context, .. } => context,
+        }
+    }
+
+    /// Set the file of the warning, unless it is already known.
+    pub fn in_file<P: Into<PathBuf>>(mut self, file: P) -> Warning {
+        self.context_mut().locate_file(file.into());
+        self
+    }
+
+    /// Set the position of the warning in the file being read, unless
+    /// it is already known or the problem is in another file.
+    pub(crate) fn at(mut self, position: xml::common::TextPosition) -> Warning {
+        self.context_mut().locate_position(position);
+        self
+    }
+
+    pub(crate) fn in_peripheral(mut self, name: &str) -> Warning {
+        Context::locate_name(&mut self.context_mut().peripheral, name);
+        self
+    }
+
+    pub(crate) fn in_register(mut self, name: &str) -> Warning {
+        Context::locate_name(&mut self.context_mut().register, name);
+        self
+    }
+
+    pub(crate) fn in_field(mut self, name: &str) -> Warning {
+        Context::locate_name(&mut self.context_mut().field, name);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.context())?;
+        match self {
+            Error::Io { source, .. } => write!(f, "{}", source),
+            Error::Xml { message, .. } => write!(f, "{}", message),
+            Error::Write { message, .. } => write!(f, "cannot write SVD: {}", message),
+            Error::UnsupportedEncoding { encoding, .. } => {
+                write!(f, "unsupported Unicode file encoding: {}", encoding)
+            }
+            Error::Syntax { message, .. } => write!(f, "{}", message),
+            Error::InvalidValue { key, value, .. } => write!(f, "invalid {} '{}'", key, value),
+            Error::FieldTooBig {
+                bit_offset,
+                bit_width,
+                register_width,
+                ..
+            } => write!(
+                f,
+                "field with offset {} and width {} too big for register of width {}",
+                bit_offset, bit_width, register_width
+            ),
+            Error::ResetValueTooBig { reset_value, .. } => {
+                write!(f, "resetval {} too big for field", reset_value)
+            }
+            Error::MissingValue { key, .. } => write!(f, "no {} given", key),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Warning::Error(error) = self {
+            return write!(f, "{}", error);
+        }
+        write!(f, "{}", self.context())?;
+        match self {
+            Warning::Error(_) => Ok(()),
+            Warning::DuplicateRegister { name, .. } => {
+                write!(f, "non-unique register name {}, appending underline", name)
+            }
+            Warning::DuplicateEnumValue { value, .. } => {
+                write!(f, "non-unique enumeration value {}, ignoring", value)
+            }
+            Warning::UnknownCpu { device, isa, .. } => write!(
+                f,
+                "no CPU information known for device '{}' with isa '{}', using a generic header",
+                device, isa
+            ),
+            Warning::UnassignedInterrupt {
+                name,
+                peripheral: Some(peripheral),
+                ..
+            } => write!(
+                f,
+                "no peripheral found for interrupt {}, adding it to {}",
+                name, peripheral
+            ),
+            Warning::UnassignedInterrupt { name, .. } => {
+                write!(f, "no peripheral found for interrupt {}, ignoring", name)
+            }
+            Warning::Overlap { other, .. } => write!(f, "overlaps {}", other),
+            Warning::EnumValueTooBig {
+                value, bit_width, ..
+            } => write!(
+                f,
+                "enumerated value {} too big for field of width {}",
+                value, bit_width
+            ),
+            Warning::DuplicateName { name, .. } => {
+                write!(f, "name {} already used, ignoring", name)
+            }
+            Warning::NameNotUnique { name, .. } => write!(f, "name {} is not unique", name),
+            Warning::EnumValueDropped {
+                value, bit_width, ..
+            } => write!(
+                f,
+                "enumerated value {} too big for field of width {}, ignoring",
+                value, bit_width
+            ),
+            Warning::EnumValueShifted { value, shifted, .. } => write!(
+                f,
+                "enumerated value {} is given as register value, using 0x{:X}",
+                value, shifted
+            ),
+            Warning::EnumValuesDerived { path, .. } => {
+                write!(f, "enumerated values derived from the identical {}", path)
+            }
+            Warning::EnumValueSplit { value, .. } => write!(
+                f,
+                "enumerated value {} named again, using it for writing",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for Warning {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Warning::Error(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for Warning {
+    fn from(error: Error) -> Warning {
+        Warning::Error(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::Io {
+            context: Box::default(),
+            source,
+        }
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(e: xml::reader::Error) -> Error {
+        let position = xml::common::Position::position(&e);
+        Error::Xml {
+            context: Box::default(),
+            message: e.msg().to_string(),
+        }
+        .at(position)
+    }
+}
+
+impl From<xml::writer::Error> for Error {
+    fn from(e: xml::writer::Error) -> Error {
+        Error::Write {
+            context: Box::default(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Error {
+        Error::Write {
+            context: Box::default(),
+            message: e.to_string(),
+        }
+    }
+}
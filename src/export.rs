@@ -0,0 +1,274 @@
+//! Writing the device model as JSON or YAML.
+//!
+//! The output has the device, peripheral, register, field and
+//! enumerated value hierarchy of the SVD output, but with the defaults
+//! resolved: each register has its absolute address, size, access and
+//! reset value, and each field its access. Register arrays are expanded
+//! into their elements, and enumerated values are numbers.
+
+use std::io;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::model::{Access, Device, EnumeratedValue, Field, Peripheral, Register};
+use crate::reader::parse_number;
+use crate::Args;
+
+#[derive(Serialize)]
+struct ExportDevice<'a> {
+    name: &'a str,
+    version: &'a str,
+    description: &'a str,
+    cpu: ExportCpu<'a>,
+    address_unit_bits: u32,
+    width: u32,
+    peripherals: Vec<ExportPeripheral<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportCpu<'a> {
+    name: &'a str,
+    revision: &'a str,
+    endian: &'a str,
+    mpu_present: bool,
+    fpu_present: bool,
+    nvic_prio_bits: u32,
+    vendor_systick_config: bool,
+}
+
+#[derive(Serialize)]
+struct ExportPeripheral<'a> {
+    name: &'a str,
+    derived_from: Option<&'a str>,
+    description: Option<&'a str>,
+    group_name: Option<&'a str>,
+    base_address: Option<u64>,
+    size: Option<u64>,
+    interrupts: Vec<ExportInterrupt<'a>>,
+    registers: Vec<ExportRegister<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportInterrupt<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    value: u32,
+}
+
+#[derive(Serialize)]
+struct ExportRegister<'a> {
+    name: String,
+    description: Option<String>,
+    address_offset: u64,
+    /// Base address of the peripheral plus the offset
+    address: Option<u64>,
+    size: u32,
+    access: &'static str,
+    modified_write_values: Option<&'static str>,
+    read_action: Option<&'static str>,
+    reset_value: u64,
+    fields: Vec<ExportField<'a>>,
+}
+
+#[derive(Clone, Serialize)]
+struct ExportField<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    bit_offset: Option<u32>,
+    bit_width: Option<u32>,
+    access: &'static str,
+    modified_write_values: Option<&'static str>,
+    read_action: Option<&'static str>,
+    enumerated_values: Vec<ExportEnumeratedValue<'a>>,
+    /// Values for writing, if they differ from those for reading
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    write_enumerated_values: Vec<ExportEnumeratedValue<'a>>,
+}
+
+#[derive(Clone, Serialize)]
+struct ExportEnumeratedValue<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    value: u64,
+}
+
+/// Export a field, which has the access and side effects of its
+/// register unless it has its own access.
+fn export_field<'a>(
+    args: &Args,
+    field: &'a Field,
+    register: &Register,
+    access: Access,
+) -> ExportField<'a> {
+    let export_values = |values: &'a [EnumeratedValue]| {
+        let mut exported = vec![];
+        for value in values {
+            match parse_number("value", &value.value) {
+                Ok(number) => exported.push(ExportEnumeratedValue {
+                    name: &value.name,
+                    description: value.description.as_deref(),
+                    value: number,
+                }),
+                Err(e) => args.warn(e.in_field(&field.name)),
+            }
+        }
+        exported
+    };
+    let (access, modified_write_values, read_action) = match field.access {
+        Some(access) => (access, field.modified_write_values, field.read_action),
+        None => (access, register.modified_write_values, register.read_action),
+    };
+    ExportField {
+        name: &field.name,
+        description: field.description.as_deref(),
+        bit_offset: field.bit_offset,
+        bit_width: field.bit_width,
+        access: access.as_str(),
+        modified_write_values: modified_write_values.map(|m| m.as_str()),
+        read_action: read_action.map(|r| r.as_str()),
+        enumerated_values: export_values(&field.enumerated_values),
+        write_enumerated_values: export_values(&field.write_enumerated_values),
+    }
+}
+
+/// Export a register, as one register per array element.
+fn export_register<'a>(
+    args: &Args,
+    device: &Device,
+    base_address: Option<u64>,
+    register: &'a Register,
+) -> Vec<ExportRegister<'a>> {
+    let access = register.access.unwrap_or(device.access);
+    let warnings = args.warning_count();
+    let fields: Vec<ExportField> = register
+        .fields
+        .iter()
+        .map(|f| export_field(args, f, register, access))
+        .collect();
+    args.locate_warnings(warnings, |w| w.in_register(&register.name));
+
+    let element = |name: String, description: Option<String>, address_offset: u64| ExportRegister {
+        name,
+        description,
+        address_offset,
+        address: base_address.map(|base| base + address_offset),
+        size: register.size.unwrap_or(device.size),
+        access: access.as_str(),
+        modified_write_values: register.modified_write_values.map(|m| m.as_str()),
+        read_action: register.read_action.map(|r| r.as_str()),
+        reset_value: register.reset_value.unwrap_or(device.reset_value),
+        fields: vec![],
+    };
+    let mut elements = match register.dim {
+        Some(ref dim) => dim
+            .indices()
+            .iter()
+            .enumerate()
+            .map(|(i, index)| {
+                element(
+                    register.name.replace("%s", index),
+                    register
+                        .description
+                        .as_ref()
+                        .map(|d| d.replace("%s", index)),
+                    register.address_offset + i as u64 * dim.dim_increment,
+                )
+            })
+            .collect(),
+        None => vec![element(
+            register.name.clone(),
+            register.description.clone(),
+            register.address_offset,
+        )],
+    };
+    if let Some((last, rest)) = elements.split_last_mut() {
+        for element in rest {
+            element.fields = fields.clone();
+        }
+        last.fields = fields;
+    }
+    elements
+}
+
+fn export_peripheral<'a>(
+    args: &Args,
+    device: &Device,
+    peripheral: &'a Peripheral,
+) -> ExportPeripheral<'a> {
+    let warnings = args.warning_count();
+    let registers = peripheral
+        .registers
+        .iter()
+        .flat_map(|r| export_register(args, device, peripheral.base_address, r))
+        .collect();
+    args.locate_warnings(warnings, |w| w.in_peripheral(&peripheral.name));
+    ExportPeripheral {
+        name: &peripheral.name,
+        derived_from: peripheral.derived_from.as_deref(),
+        description: peripheral.description.as_deref(),
+        group_name: peripheral.group_name.as_deref(),
+        base_address: peripheral.base_address,
+        size: peripheral.size,
+        interrupts: peripheral
+            .interrupts
+            .iter()
+            .map(|i| ExportInterrupt {
+                name: &i.name,
+                description: i.description.as_deref(),
+                value: i.value,
+            })
+            .collect(),
+        registers,
+    }
+}
+
+fn export_device<'a>(args: &Args, device: &'a Device) -> ExportDevice<'a> {
+    ExportDevice {
+        name: &device.name,
+        version: &device.version,
+        description: &device.description,
+        cpu: ExportCpu {
+            name: &device.cpu.name,
+            revision: &device.cpu.revision,
+            endian: &device.cpu.endian,
+            mpu_present: device.cpu.mpu_present,
+            fpu_present: device.cpu.fpu_present,
+            nvic_prio_bits: device.cpu.nvic_prio_bits,
+            vendor_systick_config: device.cpu.vendor_systick_config,
+        },
+        address_unit_bits: device.address_unit_bits,
+        width: device.width,
+        peripherals: device
+            .peripherals
+            .iter()
+            .map(|p| export_peripheral(args, device, p))
+            .collect(),
+    }
+}
+
+fn write_error(message: String) -> Error {
+    Error::Write {
+        context: Box::default(),
+        message,
+    }
+}
+
+/// Write a device as JSON.
+pub fn write_json<O>(args: &Args, device: &Device, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    serde_json::to_writer_pretty(&mut *out, &export_device(args, device))
+        .map_err(|e| write_error(e.to_string()))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write a device as YAML.
+pub fn write_yaml<O>(args: &Args, device: &Device, out: &mut O) -> Result<()>
+where
+    O: io::Write,
+{
+    serde_yaml::to_writer(out, &export_device(args, device)).map_err(|e| write_error(e.to_string()))
+}
@@ -0,0 +1,47 @@
+//! SVD `<groupName>` for peripherals.
+//!
+//! TIXML has no notion of peripheral groups, but the name of a module
+//! file tells which block a peripheral is an instance of, such as
+//! `msp430/USCI_A0__UART_Mode_2` for the UART mode of a USCI_A module.
+
+use crate::Args;
+
+/// Guess the group from the file name of a module: the part before any
+/// `__` mode suffix, without its instance number.
+fn default_group_name(module: &str) -> &str {
+    let stem = module.rsplit('/').next().unwrap_or(module);
+    let block = stem.split("__").next().unwrap_or(stem);
+    block
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end_matches('_')
+}
+
+/// Make a valid SVD identifier from a group name.
+fn sanitize_group_name(name: &str) -> String {
+    let mut group: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if group.starts_with(|c: char| c.is_ascii_digit()) {
+        group.insert(0, '_');
+    }
+    group
+}
+
+/// The group of the peripheral read from `module`, a module file path
+/// relative to the `Modules` directory and without extension. The first
+/// entry of the group map whose pattern is a prefix of the path or of
+/// the file name wins; otherwise the group is guessed from the file name.
+pub(crate) fn group_name(args: &Args, module: &str) -> Option<String> {
+    let stem = module.rsplit('/').next().unwrap_or(module);
+    let group = args
+        .group_map
+        .iter()
+        .find(|(pattern, _)| module.starts_with(pattern) || stem.starts_with(pattern))
+        .map_or_else(|| default_group_name(module), |(_, group)| group);
+    if group.is_empty() {
+        None
+    } else {
+        Some(sanitize_group_name(group))
+    }
+}
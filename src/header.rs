@@ -0,0 +1,94 @@
+//! Reading a user-supplied SVD device header.
+//!
+//! TIXML files lack most of the information needed for the SVD
+//! `<device>` header, so it may be given as the start of an SVD file,
+//! up to (but not including) the `<peripherals>` element.
+
+use std::convert::TryFrom;
+
+use xml::reader::EventReader;
+use xml::reader::XmlEvent::{Characters, EndElement, StartElement};
+
+use crate::error::{Error, Result};
+use crate::model::{Access, Device};
+use crate::reader::parse_number;
+use crate::Args;
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(Error::invalid_value(key, value)),
+    }
+}
+
+fn parse_u32(key: &str, value: &str) -> Result<u32> {
+    u32::try_from(parse_number(key, value)?).map_err(|_| Error::invalid_value(key, value))
+}
+
+fn parse_access(key: &str, value: &str) -> Result<Access> {
+    Access::from_svd(value).ok_or_else(|| Error::invalid_value(key, value))
+}
+
+fn apply_header_value(args: &Args, device: &mut Device, path: &[&str], value: &str) -> Result<()> {
+    let cpu = &mut device.cpu;
+    let key = path.last().copied().unwrap_or("");
+
+    match path {
+        ["device", "name"] => device.name = value.to_string(),
+        ["device", "version"] => device.version = value.to_string(),
+        ["device", "description"] => device.description = value.to_string(),
+        ["device", "cpu", "name"] => cpu.name = value.to_string(),
+        ["device", "cpu", "revision"] => cpu.revision = value.to_string(),
+        ["device", "cpu", "endian"] => cpu.endian = value.to_string(),
+        ["device", "cpu", "mpuPresent"] => cpu.mpu_present = parse_bool(key, value)?,
+        ["device", "cpu", "fpuPresent"] => cpu.fpu_present = parse_bool(key, value)?,
+        ["device", "cpu", "nvicPrioBits"] => cpu.nvic_prio_bits = parse_u32(key, value)?,
+        ["device", "cpu", "vendorSystickConfig"] => {
+            cpu.vendor_systick_config = parse_bool(key, value)?
+        }
+        ["device", "addressUnitBits"] => device.address_unit_bits = parse_u32(key, value)?,
+        ["device", "width"] => device.width = parse_u32(key, value)?,
+        ["device", "size"] => device.size = parse_u32(key, value)?,
+        ["device", "access"] => device.access = parse_access(key, value)?,
+        ["device", "resetValue"] => device.reset_value = parse_number(key, value)?,
+        ["device", "resetMask"] => device.reset_mask = parse_number(key, value)?,
+        unknown => {
+            if args.verbose > 0 {
+                eprintln!("Ignoring unknown device header key '{}'", unknown.join("/"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Override the device information with values from an SVD device header.
+pub fn read_device_header(args: &Args, header: &str, device: &mut Device) -> Result<()> {
+    let mut header = header.to_string();
+    // The header is usually just the start of an SVD file, so close it.
+    if !header.contains("</device>") {
+        header.push_str("</device>");
+    }
+
+    let mut path: Vec<String> = vec![];
+    for e in EventReader::new(header.as_bytes()) {
+        match e {
+            Ok(StartElement { name, .. }) => {
+                if name.local_name == "peripherals" {
+                    break;
+                }
+                path.push(name.local_name);
+            }
+            Ok(EndElement { .. }) => {
+                path.pop();
+            }
+            Ok(Characters(value)) => {
+                let keys: Vec<&str> = path.iter().map(String::as_str).collect();
+                apply_header_value(args, device, &keys, value.trim())?;
+            }
+            Err(e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
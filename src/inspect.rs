@@ -0,0 +1,196 @@
+//! Listing what a TIXML device file contains, without converting it.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent::{EndElement, StartElement};
+
+use crate::error::Result;
+use crate::reader::{
+    check_instance, parse_number, read_cpu_info, read_instance_attributes, CpuInfo,
+};
+use crate::Args;
+
+/// Whether an instance would be converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstanceStatus {
+    /// The peripheral file exists
+    Ok,
+    /// The peripheral file does not exist
+    Missing,
+    /// The instance is ignored by the conversion
+    Skipped,
+}
+
+impl InstanceStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstanceStatus::Ok => "ok",
+            InstanceStatus::Missing => "missing",
+            InstanceStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// An `<instance>` of a CPU.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstanceInfo {
+    pub id: Option<String>,
+    pub base_address: Option<u64>,
+    pub size: Option<u64>,
+    pub href: Option<String>,
+    /// The peripheral file, relative to the current directory
+    pub path: Option<PathBuf>,
+    pub status: InstanceStatus,
+    /// Why the instance is skipped
+    pub reason: Option<String>,
+}
+
+/// A CPU and its instances.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CpuInventory {
+    #[serde(flatten)]
+    pub cpu: CpuInfo,
+    pub instances: Vec<InstanceInfo>,
+}
+
+/// List the CPUs of a TIXML device file and the peripheral instances of
+/// each. Peripheral files are looked up relative to `root_path`, the
+/// path of the device file.
+pub fn inspect_device<I>(
+    args: &Args,
+    parser: xml::EventReader<I>,
+    root_path: &str,
+) -> Result<Vec<CpuInventory>>
+where
+    I: io::Read,
+{
+    let mut inventory: Vec<CpuInventory> = vec![];
+    let mut in_cpu_tag = false;
+
+    for e in parser {
+        match e? {
+            StartElement {
+                name, attributes, ..
+            } => {
+                let OwnedName { local_name, .. } = name;
+                match local_name.as_ref() {
+                    "cpu" => {
+                        in_cpu_tag = true;
+                        let index = inventory.len() as u32;
+                        inventory.push(CpuInventory {
+                            cpu: read_cpu_info(args, index, attributes),
+                            instances: vec![],
+                        });
+                    }
+                    "instance" if in_cpu_tag => {
+                        let instance = read_instance_attributes(args, attributes);
+                        let path = instance
+                            .href
+                            .as_ref()
+                            .map(|href| Path::new(root_path).with_file_name(href));
+                        let (status, reason) = match check_instance(&instance) {
+                            Err(reason) => (InstanceStatus::Skipped, Some(reason.to_string())),
+                            Ok(_) if path.as_ref().is_some_and(|p| p.is_file()) => {
+                                (InstanceStatus::Ok, None)
+                            }
+                            Ok(_) => (InstanceStatus::Missing, None),
+                        };
+                        let number = |value: &Option<String>, key| {
+                            value.as_ref().and_then(|v| parse_number(key, v).ok())
+                        };
+                        let info = InstanceInfo {
+                            base_address: number(&instance.baseaddr, "baseaddr"),
+                            size: number(&instance.size, "size"),
+                            id: instance.id,
+                            href: instance.href,
+                            path,
+                            status,
+                            reason,
+                        };
+                        if let Some(cpu) = inventory.last_mut() {
+                            cpu.instances.push(info);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            EndElement { name } if name.local_name == "cpu" => in_cpu_tag = false,
+            _ => {}
+        }
+    }
+    Ok(inventory)
+}
+
+/// Print the inventory of a device as one table per CPU.
+pub fn write_inventory_table<O>(inventory: &[CpuInventory], out: &mut O) -> io::Result<()>
+where
+    O: io::Write,
+{
+    for (i, cpu) in inventory.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "CPU {}: {} ({}) {}",
+            cpu.cpu.index, cpu.cpu.id, cpu.cpu.isa, cpu.cpu.description
+        )?;
+        let width = cpu
+            .instances
+            .iter()
+            .filter_map(|i| i.id.as_ref().map(String::len))
+            .chain(Some("Instance".len()))
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            out,
+            "  {:<width$}  {:<10}  {:<10}  {:<7}  Path",
+            "Instance",
+            "Base",
+            "Size",
+            "Status",
+            width = width
+        )?;
+        for instance in &cpu.instances {
+            let path = match instance.path {
+                Some(ref path) => path.display().to_string(),
+                None => String::new(),
+            };
+            let reason = match instance.reason {
+                Some(ref reason) => format!(" ({})", reason),
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "  {:<width$}  {:<10}  {:<10}  {:<7}  {}{}",
+                instance.id.as_deref().unwrap_or("-"),
+                instance
+                    .base_address
+                    .map(|b| format!("0x{:08X}", b))
+                    .unwrap_or_default(),
+                instance
+                    .size
+                    .map(|s| format!("0x{:X}", s))
+                    .unwrap_or_default(),
+                instance.status.as_str(),
+                path,
+                reason,
+                width = width
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the inventory of a device as JSON.
+pub fn write_inventory_json<O>(inventory: &[CpuInventory], out: &mut O) -> io::Result<()>
+where
+    O: io::Write,
+{
+    serde_json::to_writer_pretty(&mut *out, inventory)?;
+    writeln!(out)
+}
@@ -0,0 +1,400 @@
+//! Reading interrupt tables and attaching them to peripherals.
+//!
+//! TIXML device files do not describe interrupts, so they are read from
+//! one of:
+//!
+//! - a C header with a CMSIS `IRQn_Type` enum, such as the TI device
+//!   header (`.h`); exceptions with negative numbers are skipped,
+//! - a TOML file with `[[interrupt]]` tables holding `name`, `value`, and
+//!   optionally `peripheral` and `description` (`.toml`),
+//! - a CSV file with lines `name,value[,peripheral[,description]]`, and
+//!   optionally a header line starting with `name` (any other extension).
+//!
+//! An interrupt without a peripheral belongs to the peripheral with the
+//! longest name that its own name starts with, such as `UART0` for
+//! `UART0_COMB`. If there is none, it is added to the first peripheral,
+//! so that the interrupt table has no holes.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result, Warning};
+use crate::model::{Device, Interrupt};
+use crate::Args;
+
+/// An interrupt, and the name of its peripheral if it is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterruptEntry {
+    pub interrupt: Interrupt,
+    pub peripheral: Option<String>,
+}
+
+fn syntax_error(message: String) -> Error {
+    Error::Syntax {
+        context: Box::default(),
+        message,
+    }
+}
+
+/// Parse an interrupt number, which may be negative for exceptions.
+fn parse_irq_number(value: &str) -> Option<i64> {
+    let value = value.trim().trim_start_matches('(').trim_end_matches(')');
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value.trim()),
+        None => (false, value),
+    };
+    let value = value.trim_end_matches(['u', 'U']);
+    let number = if value.starts_with("0x") || value.starts_with("0X") {
+        i64::from_str_radix(&value[2..], 16).ok()?
+    } else {
+        value.parse().ok()?
+    };
+    Some(if negative { -number } else { number })
+}
+
+/// Find the body of the `IRQn_Type` enum, and the line it starts on.
+fn find_irqn_enum(header: &str) -> Option<(&str, usize)> {
+    let mut search = 0;
+    while let Some(i) = header[search..].find("IRQn_Type") {
+        let name = search + i;
+        search = name + "IRQn_Type".len();
+        let before = header[..name].trim_end();
+        if !before.ends_with('}') {
+            continue;
+        }
+        let end = before.len() - 1;
+        let start = header[..end].rfind('{')? + 1;
+        let line = header[..start].lines().count();
+        return Some((&header[start..end], line));
+    }
+    None
+}
+
+/// Read the interrupts from the `IRQn_Type` enum of a C header.
+fn parse_c_header(header: &str) -> Result<Vec<InterruptEntry>> {
+    let (body, first_line) = find_irqn_enum(header)
+        .ok_or_else(|| syntax_error("no IRQn_Type enum found".to_string()))?;
+
+    let mut entries = vec![];
+    let mut next_value = 0;
+    let mut in_comment = false;
+    for (i, line) in body.lines().enumerate() {
+        let mut code = line;
+        let mut comment = None;
+        if in_comment {
+            match code.find("*/") {
+                Some(end) => {
+                    in_comment = false;
+                    code = &code[end + 2..];
+                }
+                None => continue,
+            }
+        }
+        if let Some(start) = code.find("/*") {
+            match code[start..].find("*/") {
+                Some(end) => comment = Some(&code[start + 2..start + end]),
+                None => in_comment = true,
+            }
+            code = &code[..start];
+        } else if let Some(start) = code.find("//") {
+            comment = Some(&code[start + 2..]);
+            code = &code[..start];
+        }
+
+        let description = comment
+            .map(|c| c.trim_start_matches(['!', '*', '<']))
+            .map(str::trim)
+            .filter(|c| !c.is_empty());
+        for item in code.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (name, value) = match item.split_once('=') {
+                Some((name, value)) => match parse_irq_number(value) {
+                    Some(value) => (name.trim(), value),
+                    None => {
+                        return Err(syntax_error(format!(
+                            "unsupported interrupt number '{}'",
+                            value.trim()
+                        ))
+                        .at_line((first_line + i) as u64))
+                    }
+                },
+                None => (item, next_value),
+            };
+            next_value = value + 1;
+            if value < 0 {
+                continue;
+            }
+            entries.push(InterruptEntry {
+                interrupt: Interrupt {
+                    name: name.trim_end_matches("_IRQn").to_string(),
+                    description: description.map(str::to_string),
+                    value: value as u32,
+                },
+                peripheral: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+struct TomlInterrupts {
+    #[serde(default)]
+    interrupt: Vec<TomlInterrupt>,
+}
+
+#[derive(Deserialize)]
+struct TomlInterrupt {
+    name: String,
+    value: u32,
+    peripheral: Option<String>,
+    description: Option<String>,
+}
+
+fn parse_toml(text: &str) -> Result<Vec<InterruptEntry>> {
+    let file: TomlInterrupts =
+        toml::from_str(text).map_err(|e| syntax_error(e.message().to_string()))?;
+    Ok(file
+        .interrupt
+        .into_iter()
+        .map(|i| InterruptEntry {
+            interrupt: Interrupt {
+                name: i.name,
+                description: i.description,
+                value: i.value,
+            },
+            peripheral: i.peripheral,
+        })
+        .collect())
+}
+
+fn parse_csv(text: &str) -> Result<Vec<InterruptEntry>> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut columns = line.splitn(4, ',').map(str::trim);
+        let name = columns.next().unwrap_or_default();
+        if i == 0 && name.eq_ignore_ascii_case("name") {
+            continue;
+        }
+        let value = columns.next().unwrap_or_default();
+        let value = match parse_irq_number(value) {
+            Some(value) if value >= 0 => value as u32,
+            _ => return Err(Error::invalid_value("value", value).at_line(i as u64 + 1)),
+        };
+        let non_empty = |column: Option<&str>| column.filter(|c| !c.is_empty()).map(str::to_string);
+        let peripheral = non_empty(columns.next());
+        let description = non_empty(columns.next());
+        entries.push(InterruptEntry {
+            interrupt: Interrupt {
+                name: name.to_string(),
+                description,
+                value,
+            },
+            peripheral,
+        });
+    }
+    Ok(entries)
+}
+
+/// Read interrupts from a C header, TOML or CSV file, chosen by extension.
+pub fn read_interrupt_file<P: AsRef<Path>>(path: P) -> Result<Vec<InterruptEntry>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "h" => parse_c_header(&text),
+        "toml" => parse_toml(&text),
+        _ => parse_csv(&text),
+    }
+    .map_err(|e| e.in_file(path))
+}
+
+/// Whether an interrupt name starts with the name of a peripheral.
+fn belongs_to(interrupt: &str, peripheral: &str) -> bool {
+    let interrupt = interrupt.to_uppercase();
+    let peripheral = peripheral.to_uppercase();
+    interrupt.starts_with(&peripheral)
+        && interrupt[peripheral.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c == '_')
+}
+
+/// Add each interrupt to its peripheral. Interrupts for which no
+/// peripheral is found are added to the first one, with a warning.
+pub(crate) fn attach_interrupts(args: &Args, device: &mut Device, entries: &[InterruptEntry]) {
+    for entry in entries {
+        let interrupt = &entry.interrupt;
+        let found = match entry.peripheral {
+            Some(ref name) => device.peripherals.iter().position(|p| p.name == *name),
+            None => device
+                .peripherals
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| belongs_to(&interrupt.name, &p.name))
+                .max_by_key(|(_, p)| p.name.len())
+                .map(|(i, _)| i),
+        };
+        let i = match found {
+            Some(i) => i,
+            None => {
+                let first = device.peripherals.first().map(|p| p.name.clone());
+                args.warn(Warning::UnassignedInterrupt {
+                    context: Box::default(),
+                    name: interrupt.name.clone(),
+                    peripheral: first.clone(),
+                });
+                match first {
+                    Some(_) => 0,
+                    None => continue,
+                }
+            }
+        };
+        device.peripherals[i].interrupts.push(interrupt.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Peripheral;
+
+    fn names(entries: &[InterruptEntry]) -> Vec<(&str, u32)> {
+        entries
+            .iter()
+            .map(|e| (e.interrupt.name.as_str(), e.interrupt.value))
+            .collect()
+    }
+
+    #[test]
+    fn read_c_header() {
+        let header = "
+typedef enum IRQn
+{
+  Reset_IRQn       = -15,  /*!< 1 Reset Vector */
+  SysTick_IRQn     = -1,   // 15 System Tick
+  GPIO_IRQn        = 0,    /*!< 16 GPIO */
+  I2C_IRQn,                /*!< 17 I2C */
+  /* Reserved
+     for later */
+  UART0_COMB_IRQn  = 0x5U, /*!< 21 UART0 */
+  FLASH_IRQn
+} IRQn_Type;
+";
+        let entries = parse_c_header(header).unwrap();
+        assert_eq!(
+            names(&entries),
+            [("GPIO", 0), ("I2C", 1), ("UART0_COMB", 5), ("FLASH", 6)]
+        );
+        assert_eq!(entries[0].interrupt.description.as_deref(), Some("16 GPIO"));
+        assert_eq!(entries[3].interrupt.description, None);
+    }
+
+    #[test]
+    fn read_c_header_errors() {
+        assert!(parse_c_header("typedef enum { A } Other_Type;").is_err());
+        let error = parse_c_header("typedef enum {\n  A_IRQn = B + 1,\n} IRQn_Type;").unwrap_err();
+        assert_eq!(error.context().line, Some(2));
+    }
+
+    #[test]
+    fn read_toml() {
+        let text = r#"
+[[interrupt]]
+name = "GPIO"
+value = 0
+
+[[interrupt]]
+name = "FLASH"
+value = 7
+peripheral = "FLASH_CTRL"
+description = "Flash controller"
+"#;
+        let entries = parse_toml(text).unwrap();
+        assert_eq!(names(&entries), [("GPIO", 0), ("FLASH", 7)]);
+        assert_eq!(entries[0].peripheral, None);
+        assert_eq!(entries[1].peripheral.as_deref(), Some("FLASH_CTRL"));
+        assert_eq!(
+            entries[1].interrupt.description.as_deref(),
+            Some("Flash controller")
+        );
+        assert!(parse_toml("[[interrupt]]\nname = \"GPIO\"\n").is_err());
+    }
+
+    #[test]
+    fn read_csv() {
+        let text = "name,value,peripheral,description
+# comment
+GPIO,0
+UART0_COMB,0x5,UART0,UART0 combined, with errors
+
+FLASH,7,,Flash
+";
+        let entries = parse_csv(text).unwrap();
+        assert_eq!(
+            names(&entries),
+            [("GPIO", 0), ("UART0_COMB", 5), ("FLASH", 7)]
+        );
+        assert_eq!(entries[1].peripheral.as_deref(), Some("UART0"));
+        assert_eq!(
+            entries[1].interrupt.description.as_deref(),
+            Some("UART0 combined, with errors")
+        );
+        assert_eq!(entries[2].peripheral, None);
+        assert_eq!(entries[2].interrupt.description.as_deref(), Some("Flash"));
+    }
+
+    #[test]
+    fn read_csv_errors() {
+        let error = parse_csv("GPIO,0\nSSI0,-1\n").unwrap_err();
+        assert_eq!(error.context().line, Some(2));
+    }
+
+    #[test]
+    fn attach_to_peripherals() {
+        let args = Args::new(true, 0, false, false, false, 0);
+        let peripheral = |name: &str| Peripheral {
+            name: name.to_string(),
+            ..Peripheral::default()
+        };
+        let mut device = Device {
+            peripherals: vec![peripheral("GPIO"), peripheral("UART"), peripheral("UART0")],
+            ..Device::default()
+        };
+        let entry = |name: &str, value: u32, peripheral: Option<&str>| InterruptEntry {
+            interrupt: Interrupt {
+                name: name.to_string(),
+                description: None,
+                value,
+            },
+            peripheral: peripheral.map(str::to_string),
+        };
+        let entries = [
+            entry("UART0_COMB", 5, None),
+            entry("EVENT", 6, Some("UART")),
+            entry("FLASH", 7, None),
+        ];
+        attach_interrupts(&args, &mut device, &entries);
+        let interrupts: Vec<Vec<&str>> = device
+            .peripherals
+            .iter()
+            .map(|p| p.interrupts.iter().map(|i| i.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            interrupts,
+            [vec!["FLASH"], vec!["EVENT"], vec!["UART0_COMB"]]
+        );
+        let warnings = args.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(
+            warnings[0],
+            Warning::UnassignedInterrupt { ref peripheral, .. }
+                if peripheral.as_deref() == Some("GPIO")
+        ));
+    }
+}
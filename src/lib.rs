@@ -4,20 +4,69 @@
 /// and peripheral descriptor files.
 extern crate xml;
 
-use std::collections::HashSet;
-use xml::writer;
 use xml::writer::EmitterConfig;
 
+use std::cell::RefCell;
 use std::io;
 
-use std::fs::File;
-use std::path::Path;
-use std::str::FromStr;
-
-use xml::attribute::OwnedAttribute;
-use xml::name::OwnedName;
 use xml::reader::EventReader;
-use xml::reader::XmlEvent::{EndElement, StartElement};
+
+use interrupts::attach_interrupts;
+use sanitize::sanitize_peripheral;
+use validate::check_field_sizes;
+
+mod batch;
+mod cheader;
+mod cpus;
+mod derive;
+mod diff;
+mod dim;
+mod docs;
+mod error;
+mod export;
+mod group;
+mod header;
+mod inspect;
+mod interrupts;
+mod model;
+#[cfg(feature = "pac")]
+mod pac;
+mod patch;
+mod reader;
+mod sanitize;
+mod svd;
+mod validate;
+
+pub use batch::{process_device_cpus, process_devices_dir, write_batch_summary, BatchResult};
+pub use cheader::write_c_header;
+pub use derive::{derive_enumerated_values, derive_peripherals};
+pub use diff::{diff_devices, diff_module_dirs, write_diff, DiffEntry, DiffKind};
+pub use dim::make_register_arrays;
+pub use docs::{write_docs, DocFormat};
+pub use error::{Context, Error, Result, Warning};
+pub use export::{write_json, write_yaml};
+pub use header::read_device_header;
+pub use inspect::{
+    inspect_device, write_inventory_json, write_inventory_table, CpuInventory, InstanceInfo,
+    InstanceStatus,
+};
+pub use interrupts::{read_interrupt_file, InterruptEntry};
+pub use model::{
+    Access, Cpu, Device, Dim, EnumeratedValue, Field, Interrupt, ModifiedWriteValues, Peripheral,
+    ReadAction, Register, Usage,
+};
+#[cfg(feature = "pac")]
+pub use pac::write_pac;
+pub use patch::{
+    apply_patch, builtin_patch, read_patch_file, EnumPatch, FieldPatch, Patch, PeripheralPatch,
+    RegisterPatch,
+};
+pub use reader::{
+    get_parser_from_filename, open_tixml_file, read_cpus, read_device, read_peripheral, CpuInfo,
+};
+pub use sanitize::EnumDuplicates;
+pub use svd::{write_device, write_peripheral};
+pub use validate::validate_device;
 
 /// This structure contains arguments used to customize the behavior of tixml2svd.
 pub struct Args {
@@ -33,6 +82,26 @@ pub struct Args {
     no_device_info: bool,
     // If there are several CPUs, read peripherals from CPU 0, 1, or 2, for example.
     cpunum: u32,
+    // Convert each CPU of a device into its own SVD file
+    all_cpus: bool,
+    // Combine repeated registers into register arrays
+    dim_arrays: bool,
+    // Let fields with identical enumerated values derive them from the first
+    derive_enums: bool,
+    // Patterns and names for peripheral groups, tried in order
+    group_map: Vec<(String, String)>,
+    // Interrupts to add to the peripherals of a device
+    interrupts: Vec<InterruptEntry>,
+    // Check converted devices, and report problems as warnings
+    validate: bool,
+    // Apply the built-in fixes for known mistakes in TIXML files
+    builtin_patches: bool,
+    // Patches to apply after the built-in ones
+    patches: Vec<Patch>,
+    // What sanitizing does with enumerated values whose value is used twice
+    enum_duplicates: EnumDuplicates,
+    // Warnings reported since the last call to take_warnings
+    warnings: RefCell<Vec<Warning>>,
 }
 
 impl Args {
@@ -44,577 +113,263 @@ impl Args {
         no_device_info: bool,
         cpunum: u32,
     ) -> Args {
-        let a = Args {
+        Args {
             silent,
             verbose,
             peripheral_only,
             sanitize,
             no_device_info,
             cpunum,
-        };
-        a
+            all_cpus: false,
+            dim_arrays: false,
+            derive_enums: false,
+            group_map: vec![],
+            interrupts: vec![],
+            validate: false,
+            builtin_patches: true,
+            patches: vec![],
+            enum_duplicates: EnumDuplicates::Drop,
+            warnings: RefCell::new(vec![]),
+        }
     }
-}
 
-fn get_name_from_description(description: &str) -> String {
-    let replaces = [
-        (',', '_'),
-        ('.', '_'),
-        (':', '\0'),
-        ('/', '_'),
-        ('#', '\0'),
-        ('-', '_'),
-        (' ', '_'),
-    ];
-    let mut name = description
-        .replace('-', "")
-        .split_whitespace()
-        .take(3)
-        .collect::<Vec<&str>>()
-        .join("_");
-    name = name.split("#br#").next().unwrap_or("").to_string();
-    name = name.split(':').next().unwrap_or("").to_string();
-    for (from, to) in &replaces {
-        name = name.replace(&from.to_string(), &to.to_string());
+    /// When converting into a directory, write one SVD file for each CPU
+    /// of a device, instead of one for the CPU selected by `cpunum`.
+    pub fn with_all_cpus(mut self, all_cpus: bool) -> Args {
+        self.all_cpus = all_cpus;
+        self
     }
-    name.to_uppercase()
-}
 
-fn write_access<O>(
-    args: &Args,
-    xml_out: &mut xml::EventWriter<&mut O>,
-    ti_access: &str,
-) -> io::Result<()>
-where
-    O: io::Write,
-{
-    let access = match ti_access {
-        "RO" => "read-only",
-        "WO" => "write-only",
-        "RW" => "read-write",
-        "R=1/W=0" => "read-only",
-        "R=0/W=1" => "write-only",
-        "R=1/W=1" => "read-write",
-        "R" => "read-only",
-        "W" => "write-only",
-        "R/W" => "read-write",
-        "R/W1TC" => "read-write",
-        "R/W1TS" => "read-write",
-        unknown => {
-            if !args.silent {
-                eprintln!("Ignoring unknown access key '{}'", unknown);
-            }
-            return Ok(());
-        }
-    };
+    /// Combine runs of repeated registers into SVD register arrays.
+    pub fn with_dim_arrays(mut self, dim_arrays: bool) -> Args {
+        self.dim_arrays = dim_arrays;
+        self
+    }
 
-    write_tag(args, xml_out, "access", access)
-}
+    /// Write identical enumerated values of a peripheral only once, and
+    /// let the other fields with them use `derivedFrom`.
+    pub fn with_derive_enums(mut self, derive_enums: bool) -> Args {
+        self.derive_enums = derive_enums;
+        self
+    }
 
-fn write_start<O>(
-    args: &Args,
-    xml_out: &mut xml::EventWriter<&mut O>,
-    element: &str,
-) -> io::Result<()>
-where
-    O: io::Write,
-{
-    let event: writer::XmlEvent = writer::XmlEvent::start_element(element).into();
-    if args.verbose > 2 {
-        eprintln!("Writing start-tag: {:?}", event);
+    /// Put peripherals whose module file path or name starts with a
+    /// pattern into the group of that name, instead of guessing the
+    /// group from the file name.
+    pub fn with_group_map(mut self, group_map: Vec<(String, String)>) -> Args {
+        self.group_map = group_map;
+        self
     }
-    match xml_out.write(event) {
-        Ok(x) => Ok(x),
-        Err(x) => Err(io::Error::new(io::ErrorKind::Other, x.to_string())),
+
+    /// Add interrupts, such as those from `read_interrupt_file`, to the
+    /// peripherals of each device.
+    pub fn with_interrupts(mut self, interrupts: Vec<InterruptEntry>) -> Args {
+        self.interrupts = interrupts;
+        self
     }
-}
 
-fn write_comment<O>(
-    args: &Args,
-    xml_out: &mut xml::EventWriter<&mut O>,
-    data: &str,
-) -> io::Result<()>
-where
-    O: io::Write,
-{
-    let event: writer::XmlEvent = writer::XmlEvent::comment(data).into();
-    if args.verbose > 2 {
-        eprintln!("Writing comment: {:?}", event);
+    /// Check each converted device with `validate_device`, and report the
+    /// problems found as warnings.
+    pub fn with_validate(mut self, validate: bool) -> Args {
+        self.validate = validate;
+        self
     }
-    match xml_out.write(event) {
-        Ok(x) => Ok(x),
-        Err(x) => Err(io::Error::new(io::ErrorKind::Other, x.to_string())),
+
+    /// Apply the built-in fixes from `builtin_patch` to each device, which
+    /// is the default.
+    pub fn with_builtin_patches(mut self, builtin_patches: bool) -> Args {
+        self.builtin_patches = builtin_patches;
+        self
     }
-}
 
-fn write_content<O>(
-    args: &Args,
-    xml_out: &mut xml::EventWriter<&mut O>,
-    content: &str,
-) -> io::Result<()>
-where
-    O: io::Write,
-{
-    let event: writer::XmlEvent = writer::XmlEvent::characters(content).into();
-    if args.verbose > 2 {
-        eprintln!("Writing content: {:?}", event);
+    /// Apply patches, such as those from `read_patch_file`, to each
+    /// device, after the built-in ones.
+    pub fn with_patches(mut self, patches: Vec<Patch>) -> Args {
+        self.patches = patches;
+        self
+    }
+
+    /// Select what sanitizing does with an enumerated value whose value
+    /// is already used in its field; the default is to drop it.
+    pub fn with_enum_duplicates(mut self, enum_duplicates: EnumDuplicates) -> Args {
+        self.enum_duplicates = enum_duplicates;
+        self
     }
-    match xml_out.write(event) {
-        Ok(x) => Ok(x),
-        Err(x) => Err(io::Error::new(io::ErrorKind::Other, x.to_string())),
+
+    /// A copy of these arguments that selects another CPU, with no
+    /// warnings reported yet.
+    pub(crate) fn for_cpu(&self, cpunum: u32) -> Args {
+        Args {
+            silent: self.silent,
+            verbose: self.verbose,
+            peripheral_only: self.peripheral_only,
+            sanitize: self.sanitize,
+            no_device_info: self.no_device_info,
+            cpunum,
+            all_cpus: self.all_cpus,
+            dim_arrays: self.dim_arrays,
+            derive_enums: self.derive_enums,
+            group_map: self.group_map.clone(),
+            interrupts: self.interrupts.clone(),
+            validate: self.validate,
+            builtin_patches: self.builtin_patches,
+            patches: self.patches.clone(),
+            enum_duplicates: self.enum_duplicates,
+            warnings: RefCell::new(vec![]),
+        }
+    }
+
+    /// Report a problem that does not stop the conversion.
+    pub(crate) fn warn<W: Into<Warning>>(&self, warning: W) {
+        self.warnings.borrow_mut().push(warning.into());
+    }
+
+    /// The number of warnings reported so far.
+    pub(crate) fn warning_count(&self) -> usize {
+        self.warnings.borrow().len()
+    }
+
+    /// Add location information to the warnings reported since `since`.
+    pub(crate) fn locate_warnings<F>(&self, since: usize, locate: F)
+    where
+        F: Fn(Warning) -> Warning,
+    {
+        let mut warnings = self.warnings.borrow_mut();
+        let since = since.min(warnings.len());
+        let located: Vec<Warning> = warnings.drain(since..).map(locate).collect();
+        warnings.extend(located);
+    }
+
+    /// Return the warnings reported so far, and forget them.
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        self.warnings.replace(vec![])
     }
 }
 
-fn write_end<O>(args: &Args, xml_out: &mut xml::EventWriter<&mut O>) -> io::Result<()>
-where
-    O: io::Write,
-{
-    let event: writer::XmlEvent = writer::XmlEvent::end_element().into();
-    if args.verbose > 2 {
-        eprintln!("Writing end-tag: {:?}", event);
+/// Sanitize a peripheral and share its identical enumerated values, as
+/// selected in `args`. Register arrays are combined before this, both for
+/// devices and single peripherals, so that both get the same output.
+fn finish_peripheral(args: &Args, peripheral: &mut Peripheral) {
+    if args.sanitize {
+        sanitize_peripheral(args, peripheral);
     }
-    match xml_out.write(event) {
-        Ok(x) => Ok(x),
-        Err(x) => Err(io::Error::new(io::ErrorKind::Other, x.to_string())),
+    if args.derive_enums {
+        derive_enumerated_values(peripheral);
     }
 }
 
-fn write_tag<O>(
+/// Read a TIXML device file, optionally taking the device information
+/// from an SVD device header. Peripheral files are looked up relative
+/// to `root_path`, the path of the device file.
+pub fn load_device<I>(
     args: &Args,
-    xml_out: &mut xml::EventWriter<&mut O>,
-    element: &str,
-    content: &str,
-) -> io::Result<()>
+    fin: I,
+    root_path: &str,
+    device_header: Option<&str>,
+) -> Result<Device>
 where
-    O: io::Write,
+    I: io::Read,
 {
-    write_start(args, xml_out, element)?;
-    write_content(args, xml_out, content)?;
-    write_end(args, xml_out)?;
-    Ok(())
-}
+    let parser = EventReader::new(fin);
+    let warnings = args.warning_count();
 
-/// Used by process_device_base to open each peripheral file and
-/// provide a xml parser for the file. It only makes sense to replace
-/// this if you wish to run this code without file-based storage.
-pub fn get_parser_from_filename(
-    root: &str,
-    filename: &str,
-) -> io::Result<xml::EventReader<std::fs::File>> {
-    let root_path = Path::new(root);
-    let concat_path = root_path.with_file_name(filename);
-    let fd_periph = File::open(&concat_path)?;
-    Ok(EventReader::new(fd_periph))
+    let result = load_device_base(args, parser, device_header, &|x| {
+        get_parser_from_filename(root_path, x)
+    });
+    args.locate_warnings(warnings, |w| w.in_file(root_path));
+    result.map_err(|e| e.in_file(root_path))
 }
 
-/// Used by process_device_base to convert the TIXML <device> header
-/// to the corresponding SVD <device> fields.
-fn generate_device<O>(
+/// Read a TIXML device, and prepare it for output as selected in `args`.
+pub fn load_device_base<I>(
     args: &Args,
-    mut xml_out: &mut xml::EventWriter<&mut O>,
-    device_attributes: &Vec<OwnedAttribute>,
-    cpu_attributes: &Vec<OwnedAttribute>,
-    endianness: &Option<String>,
-) -> io::Result<()>
+    parser: xml::EventReader<I>,
+    device_header: Option<&str>,
+    fname2parser: &dyn Fn(&str) -> io::Result<xml::EventReader<std::fs::File>>,
+) -> Result<Device>
 where
-    O: io::Write,
+    I: io::Read,
 {
-    if args.no_device_info {
-        return Ok(());
+    let mut device = read_device(args, parser, fname2parser)?;
+    // Patches select devices by their TIXML name, so apply them first
+    if args.builtin_patches {
+        apply_patch(args, &builtin_patch(), &mut device);
     }
-
-    let mut f_id: Option<&str> = None;
-    let mut f_hw_revision: Option<&str> = None;
-    let mut f_description: Option<&str> = None;
-    let mut f_isa: Option<String> = None;
-
-    for attr in device_attributes {
-        let xml::attribute::OwnedAttribute { name, value } = attr;
-        let OwnedName {
-            local_name: attr_name,
-            ..
-        } = name;
-        match attr_name.as_ref() {
-            "id" => {
-                if value.len() > 0 {
-                    f_id = Some(&value)
-                }
-            }
-            "description" => {
-                if value.len() > 0 {
-                    f_description = Some(&value)
-                }
-            }
-            _ => {}
-        }
+    for patch in &args.patches {
+        apply_patch(args, patch, &mut device);
     }
-
-    for attr in cpu_attributes {
-        let xml::attribute::OwnedAttribute { name, value } = attr;
-        let OwnedName {
-            local_name: attr_name,
-            ..
-        } = name;
-        match attr_name.as_ref() {
-            "HW_revision" => {
-                if value.len() > 0 {
-                    f_hw_revision = Some(&value)
-                }
-            }
-            "isa" => {
-                if value.len() > 0 {
-                    f_isa = Some(if args.sanitize {
-                        value.replace("Cortex_", "C")
-                    } else {
-                        value.to_string()
-                    })
-                }
-            }
-            _ => {}
-        }
+    // The header may set the default register size
+    if let Some(header) = device_header {
+        read_device_header(args, header, &mut device)?;
     }
-
-    write_tag(args, &mut xml_out, "name", f_id.unwrap_or("[unknown CPU]"))?;
-    write_tag(
-        args,
-        &mut xml_out,
-        "version",
-        f_hw_revision.unwrap_or("0.0"),
-    )?;
-    write_tag(
-        args,
-        &mut xml_out,
-        "description",
-        f_description.unwrap_or(""),
-    )?;
-    write_start(args, &mut xml_out, "cpu")?;
-    write_tag(
-        args,
-        &mut xml_out,
-        "name",
-        f_isa.as_deref().unwrap_or("other"),
-    )?;
-    write_tag(
-        args,
-        &mut xml_out,
-        "revision",
-        f_hw_revision.unwrap_or("0.0"),
-    )?;
-    write_tag(
-        args,
-        &mut xml_out,
-        "endian",
-        endianness.as_deref().unwrap_or("other"),
-    )?;
-    write_tag(args, &mut xml_out, "mpuPresent", "true")?;
-    write_tag(args, &mut xml_out, "fpuPresent", "true")?;
-    write_tag(args, &mut xml_out, "nvicPrioBits", "3")?;
-    write_tag(args, &mut xml_out, "vendorSystickConfig", "false")?;
-    write_end(args, &mut xml_out)?;
-    write_tag(args, &mut xml_out, "addressUnitBits", "8")?;
-    write_tag(args, &mut xml_out, "width", "32")?;
-    write_tag(args, &mut xml_out, "size", "32")?;
-    write_tag(args, &mut xml_out, "access", "read-write")?;
-    write_tag(args, &mut xml_out, "resetValue", "0x00000000")?;
-    write_tag(args, &mut xml_out, "resetMask", "0xFFFFFFFF")
-}
-
-fn check_endianness(args: &Args, attributes: &Vec<OwnedAttribute>) -> Option<String> {
-    let mut f_type: Option<&str> = None;
-    let mut f_value: Option<&str> = None;
-    let mut f_id: Option<&str> = None;
-
-    for attr in attributes {
-        let xml::attribute::OwnedAttribute { name, value } = attr;
-        let value = if args.sanitize { value.trim() } else { value };
-        let OwnedName {
-            local_name: attr_name,
-            ..
-        } = name;
-        match attr_name.as_ref() {
-            "Type" => {
-                if value.len() > 0 {
-                    f_type = Some(value)
-                }
-            }
-            "Value" => {
-                if value.len() > 0 {
-                    f_value = Some(value)
-                }
-            }
-            "id" => {
-                if value.len() > 0 {
-                    f_id = Some(value)
-                }
-            }
-            _ => {}
-        }
+    for peripheral in &device.peripherals {
+        check_field_sizes(peripheral, device.size)?;
     }
-
-    f_type
-        .filter(|t| *t == "stringfield")
-        .and(f_id.filter(|t| *t == "Endianness"))
-        .and(f_value)
-        .map(|e| e.to_string())
+    attach_interrupts(args, &mut device, &args.interrupts);
+    if args.dim_arrays {
+        device.peripherals.iter_mut().for_each(make_register_arrays);
+    }
+    derive_peripherals(&mut device);
+    // Derived peripherals are not written with their registers
+    device
+        .peripherals
+        .iter_mut()
+        .filter(|p| p.derived_from.is_none())
+        .for_each(|p| finish_peripheral(args, p));
+    if args.validate {
+        validate_device(&device)
+            .into_iter()
+            .for_each(|e| args.warn(e));
+    }
+    Ok(device)
 }
 
-/// Convert a TIXML device to SVD.
-pub fn process_device<I, O>(args: &Args, fin: I, root_path: &str, fout: &mut O) -> io::Result<()>
+/// Write a device as an SVD file.
+pub fn write_svd<O>(args: &Args, device: &Device, fout: &mut O) -> Result<()>
 where
-    I: io::Read,
     O: io::Write,
 {
     let mut xml_out = EmitterConfig::new()
         .perform_indent(true)
         .create_writer(fout);
-    let parser = EventReader::new(fin);
+    write_device(args, device, &mut xml_out)
+}
 
-    process_device_base(args, parser, &mut xml_out, &|x| {
-        get_parser_from_filename(root_path, x)
-    })
+/// Convert a TIXML device to SVD, optionally taking the device
+/// information from an SVD device header.
+pub fn process_device<I, O>(
+    args: &Args,
+    fin: I,
+    root_path: &str,
+    device_header: Option<&str>,
+    fout: &mut O,
+) -> Result<()>
+where
+    I: io::Read,
+    O: io::Write,
+{
+    let device = load_device(args, fin, root_path, device_header)?;
+    write_svd(args, &device, fout)
 }
 
 /// Convert a TIXML device to SVD.
 pub fn process_device_base<I, O>(
     args: &Args,
     parser: xml::EventReader<I>,
-    mut xml_out: &mut xml::EventWriter<&mut O>,
+    device_header: Option<&str>,
+    xml_out: &mut xml::EventWriter<&mut O>,
     fname2parser: &dyn Fn(&str) -> io::Result<xml::EventReader<std::fs::File>>,
-) -> io::Result<()>
+) -> Result<()>
 where
     I: io::Read,
     O: io::Write,
 {
-    let mut printed_peripherals_tag = true;
-    let mut in_cpu_tag = false;
-    let mut cpunum = 0;
-    let mut endianness: Option<String> = None;
-    let mut device_attributes: Vec<OwnedAttribute> = vec![];
-
-    for e in parser {
-        match e {
-            Ok(StartElement {
-                name,
-                attributes,
-                namespace: _namespace,
-            }) => {
-                if args.verbose > 0 {
-                    eprintln!("Processing StartElement: {}", name);
-                }
-                let OwnedName {
-                    local_name,
-                    namespace: _,
-                    prefix: _,
-                } = name;
-                match local_name.as_ref() {
-                    "device" => {
-                        write_start(args, &mut xml_out, "device")?;
-                        write_comment(
-                            args,
-                            &mut xml_out,
-                            "Created by tixml2svd; https://github.com/dhoove/tixml2svd",
-                        )?;
-
-                        device_attributes = attributes;
-                    }
-                    "cpu" => {
-                        in_cpu_tag = true;
-                        if cpunum != args.cpunum {
-                            continue;
-                        }
-                        generate_device(
-                            args,
-                            &mut xml_out,
-                            &device_attributes,
-                            &attributes,
-                            &endianness,
-                        )?;
-                        printed_peripherals_tag = false;
-                    }
-                    "property" => {
-                        if !in_cpu_tag {
-                            continue;
-                        }
-
-                        endianness = endianness.or_else(|| check_endianness(args, &attributes));
-                    }
-                    "instance" => {
-                        if !in_cpu_tag | (cpunum != args.cpunum) {
-                            if args.verbose > 0 {
-                                eprintln!(
-                                    "Skipping cpu instance; in_cpu_tag='{}', cpunum='{}'",
-                                    in_cpu_tag, cpunum
-                                );
-                            }
-                            continue;
-                        }
-
-                        let mut f_baseaddr: Option<String> = None;
-                        let mut _f_endaddr: Option<String> = None;
-                        let mut f_size: Option<String> = None;
-                        let mut f_id: Option<String> = None;
-                        let mut f_href: Option<String> = None;
-
-                        for attr in attributes {
-                            let xml::attribute::OwnedAttribute { name, value } = attr;
-                            let value = if args.sanitize {
-                                String::from(value.trim())
-                            } else {
-                                value
-                            };
-                            let OwnedName {
-                                local_name: attr_name,
-                                ..
-                            } = name;
-                            match attr_name.as_ref() {
-                                "baseaddr" => {
-                                    if value.len() > 0 {
-                                        f_baseaddr = Some(value)
-                                    }
-                                }
-                                "endaddr" => {
-                                    if value.len() > 0 {
-                                        _f_endaddr = Some(value)
-                                    }
-                                }
-                                "size" => {
-                                    if value.len() > 0 {
-                                        f_size = Some(value)
-                                    }
-                                }
-                                "id" => {
-                                    if value.len() > 0 {
-                                        f_id = Some(if args.sanitize {
-                                            value.replace("-", "_")
-                                        } else {
-                                            value
-                                        })
-                                    }
-                                }
-                                "href" => {
-                                    if value.len() > 0 {
-                                        f_href = Some(value)
-                                    }
-                                }
-                                unknown => {
-                                    if args.verbose > 0 {
-                                        eprintln!(
-                                            "Ignoring unknown key '{}' for '{}'",
-                                            unknown, local_name
-                                        );
-                                    };
-                                }
-                            };
-                        }
-
-                        let skip = match f_href {
-                            Some(ref href) => !href.clone().starts_with("../Modules/"),
-                            None => true,
-                        };
-
-                        if let Some(id) = f_id {
-                            // If no ID present, ignore the module (TI-internal?)
-                            if skip {
-                                eprintln!("Sub-instance href does not start with Modules, or is missing. Skipping: '{:?}'", id);
-                            } else if id == "Cp15" || id == "Vfp" {
-                                eprintln!(
-                                    "Peripheral id {:?} suggests co-processor registers; Ignoring",
-                                    id
-                                );
-                            } else {
-                                if id.len() > 0 {
-                                    if !printed_peripherals_tag {
-                                        write_start(args, &mut xml_out, "peripherals")?;
-                                        printed_peripherals_tag = true;
-                                    }
-
-                                    write_start(args, &mut xml_out, "peripheral")?;
-                                    write_tag(args, &mut xml_out, "name", &id)?;
-
-                                    if let Some(baseaddr) = f_baseaddr {
-                                        write_tag(args, &mut xml_out, "baseAddress", &baseaddr)?;
-                                    }
-
-                                    match f_size {
-                                        Some(size) => {
-                                            write_start(args, &mut xml_out, "addressBlock")?;
-                                            write_tag(args, &mut xml_out, "offset", "0")?;
-                                            write_tag(args, &mut xml_out, "size", &size)?;
-                                            write_tag(args, &mut xml_out, "usage", "registers")?;
-                                            write_end(args, &mut xml_out)?;
-                                        }
-                                        None => {
-                                            if !args.silent {
-                                                eprintln!(
-                                                    "Peripheral has no size for {}",
-                                                    local_name
-                                                );
-                                            }
-                                        }
-                                    }
-
-                                    if let Some(href) = f_href {
-                                        if !args.silent {
-                                            eprintln!("Processing peripheral file: {:?}", &href);
-                                        }
-                                        let parser = fname2parser(&href)?;
-                                        process_peripheral_base(&args, parser, &mut xml_out)?;
-                                    }
-
-                                    write_end(args, &mut xml_out)?;
-                                }
-                            }
-                        }
-                    }
-                    unknown => {
-                        if args.verbose > 0 {
-                            eprintln!("Ignoring unknown start element key '{}'", unknown);
-                        }
-                    }
-                }
-            }
-
-            Ok(EndElement { name }) => {
-                if args.verbose > 0 {
-                    eprintln!("Processing EndElement: {}", name);
-                }
-                let OwnedName { local_name, .. } = name;
-                match local_name.as_ref() {
-                    "device" => {
-                        write_end(args, &mut xml_out)?;
-                    }
-                    "cpu" => {
-                        if cpunum == args.cpunum {
-                            if printed_peripherals_tag {
-                                write_end(args, &mut xml_out)?;
-                            }
-
-                            printed_peripherals_tag = true;
-                        }
-
-                        in_cpu_tag = false;
-                        cpunum += 1;
-                    }
-                    "instance" => {}
-                    unknown => {
-                        if args.verbose > 0 {
-                            eprintln!("Ignoring unknown end element key '{}'", unknown);
-                        }
-                    }
-                }
-            }
-
-            Err(e) => {
-                return Err(io::Error::new(io::ErrorKind::Other, e.to_string()));
-            }
-            _ => {}
-        }
-    }
-    Ok(())
+    let device = load_device_base(args, parser, device_header, fname2parser)?;
+    write_device(args, &device, xml_out)
 }
 
 /// Convert a TIXML peripheral to SVD.
-pub fn process_peripheral<I, O>(args: &Args, fin: I, fout: &mut O) -> io::Result<()>
+pub fn process_peripheral<I, O>(args: &Args, fin: I, fout: &mut O) -> Result<()>
 where
     I: io::Read,
     O: io::Write,
@@ -631,571 +386,18 @@ where
 pub fn process_peripheral_base<I, O>(
     args: &Args,
     parser: xml::EventReader<I>,
-    mut xml_out: &mut xml::EventWriter<&mut O>,
-) -> io::Result<()>
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
 where
     I: io::Read,
     O: io::Write,
 {
-    let mut printed_registers_tag = false;
-
-    let mut printed_fields_tag = false;
-
-    #[allow(non_snake_case)]
-    let mut printed_enumeratedValues_tag = false;
-
-    // Temporary storage to check for resetval overflow
-    let mut register_width = None;
-
-    let mut register_reset_value = None;
-
-    let mut f_used_registers = None;
-
-    let mut f_used_enumerations = None;
-    let mut f_parent_reg_name = None;
-
-    for e in parser {
-        match e {
-            Ok(StartElement {
-                name,
-                attributes,
-                namespace: _,
-            }) => {
-                if args.verbose > 0 {
-                    eprintln!("Processing StartElement: {}", name);
-                }
-                let OwnedName { local_name, .. } = name;
-                match local_name.as_ref() {
-                    "module" => {
-                        if args.sanitize {
-                            f_used_registers = Some(HashSet::new());
-                        }
-
-                        if args.peripheral_only {
-                            write_start(args, &mut xml_out, "peripheral")?;
-                        }
-                        printed_registers_tag = false;
-                        for attr in attributes {
-                            let xml::attribute::OwnedAttribute { name, value } = attr;
-                            let value = if args.sanitize {
-                                String::from(value.trim())
-                            } else {
-                                value
-                            };
-                            let OwnedName {
-                                local_name: attr_name,
-                                ..
-                            } = name;
-                            match attr_name.as_ref() {
-                                "HW_revision" => (),
-                                "XML_version" => (),
-                                "noNamespaceSchemaLocation" => (),
-                                "id" => {
-                                    if args.peripheral_only {
-                                        write_tag(args, &mut xml_out, "name", &value)?;
-                                    }
-                                }
-                                "value" => {
-                                    if args.peripheral_only {
-                                        write_tag(args, &mut xml_out, "value", &value)?;
-                                    }
-                                }
-                                "token" => (),
-                                "description" => {
-                                    write_tag(args, &mut xml_out, "description", &value)?;
-                                }
-                                unknown => {
-                                    if args.verbose > 0 {
-                                        eprintln!(
-                                            "Ignoring unknown key '{}' for '{}'",
-                                            unknown, local_name
-                                        );
-                                    };
-                                }
-                            };
-                        }
-                    }
-
-                    "register" => {
-                        let mut f_id: Option<String> = None;
-                        let mut f_value: Option<String> = None;
-                        let mut f_width: Option<String> = None;
-                        let mut f_description: Option<String> = None;
-                        // Assume access is read-write if not specified, and let further restrictions be applied by the bitfilelds
-                        let mut f_rwaccess: Option<String> = Some("RW".to_string());
-                        let mut f_offset: Option<String> = None;
-                        let mut f_resetval: Option<String> = None;
-
-                        if !printed_registers_tag {
-                            printed_registers_tag = true;
-                            write_start(args, &mut xml_out, "registers")?;
-                        }
-
-                        write_start(args, &mut xml_out, "register")?;
-                        printed_fields_tag = false;
-                        register_reset_value = None;
-
-                        for attr in attributes {
-                            let xml::attribute::OwnedAttribute { name, value } = attr;
-                            let value = if args.sanitize {
-                                String::from(value.trim())
-                            } else {
-                                value
-                            };
-                            let OwnedName {
-                                local_name: attr_name,
-                                ..
-                            } = name;
-                            match attr_name.as_ref() {
-                                "id" => {
-                                    if value.len() > 0 {
-                                        f_id = Some(value)
-                                    }
-                                }
-                                "value" => {
-                                    if value.len() > 0 {
-                                        f_value = Some(value)
-                                    }
-                                }
-                                "width" => {
-                                    if value.len() > 0 {
-                                        f_width = Some(value)
-                                    }
-                                }
-                                "acronym" => (),
-                                "description" => {
-                                    if value.len() > 0 {
-                                        f_description = Some(value)
-                                    }
-                                }
-                                "rwaccess" => {
-                                    if value.len() > 0 {
-                                        f_rwaccess = Some(value)
-                                    }
-                                }
-                                "offset" => {
-                                    if value.len() > 0 {
-                                        f_offset = Some(value)
-                                    }
-                                }
-                                "resetval" => {
-                                    if value.len() > 0 {
-                                        f_resetval = Some(value)
-                                    }
-                                }
-                                unknown => {
-                                    if args.verbose > 0 {
-                                        eprintln!(
-                                            "Ignoring unknown key '{}' for '{}'",
-                                            unknown, local_name
-                                        );
-                                    };
-                                }
-                            };
-                        }
-
-                        if let Some(id) = f_id.clone() {
-                            let unique_name = match f_used_registers {
-                                Some(ref mut used_registers) => {
-                                    let mut regname = id;
-                                    while !used_registers.insert(regname.clone()) {
-                                        eprintln!(
-                                            "Non-unique register name {}. Appending underline.",
-                                            regname
-                                        );
-                                        regname.push('_');
-                                    }
-                                    regname
-                                }
-                                None => id,
-                            };
-                            f_parent_reg_name = Some(unique_name.clone());
-                            write_tag(args, &mut xml_out, "name", &unique_name)?;
-                        }
-                        if let Some(value) = f_value {
-                            write_tag(args, &mut xml_out, "value", &value)?;
-                        }
-                        if let Some(offset) = f_offset {
-                            write_tag(args, &mut xml_out, "addressOffset", &offset)?;
-                        }
-                        if let Some(width) = f_width {
-                            let w: u32 = width.parse().unwrap();
-                            register_width = Some(w);
-                            write_tag(args, &mut xml_out, "size", &width)?;
-                        }
-                        if let Some(description) = f_description {
-                            write_tag(args, &mut xml_out, "description", &description)?;
-                        } else {
-                            if let Some(id) = f_id {
-                                write_tag(args, &mut xml_out, "description", &id)?;
-                            } else {
-                                write_tag(args, &mut xml_out, "description", "--")?;
-                            }
-                        }
-                        if let Some(rwaccess) = f_rwaccess {
-                            write_access(args, &mut xml_out, &rwaccess)?;
-                        }
-                        if let Some(resetval) = f_resetval {
-                            let resetval: u64 = resetval.parse().unwrap();
-                            register_reset_value = Some(resetval);
-                        }
-                    }
-
-                    "bitfield" => {
-                        if !printed_fields_tag {
-                            printed_fields_tag = true;
-                            write_start(args, &mut xml_out, "fields")?;
-                        }
-
-                        write_start(args, &mut xml_out, "field")?;
-                        printed_enumeratedValues_tag = false;
-
-                        let mut f_name: Option<String> = None;
-                        let mut f_range: Option<String> = None;
-                        let mut f_begin: Option<u32> = None;
-                        let mut f_width: Option<u32> = None;
-                        let mut f_end: Option<u32> = None;
-                        let mut f_rwaccess: Option<String> = None;
-                        let mut f_description: Option<String> = None;
-                        let mut f_reset_value: Option<u64> = None;
-
-                        for attr in attributes {
-                            let xml::attribute::OwnedAttribute { name, value } = attr;
-                            let value = if args.sanitize {
-                                String::from(value.trim())
-                            } else {
-                                value
-                            };
-                            let OwnedName {
-                                local_name: attr_name,
-                                ..
-                            } = name;
-                            match attr_name.as_ref() {
-                                "id" => {
-                                    if value.len() > 0 {
-                                        f_name = Some(value)
-                                    }
-                                }
-                                "range" => {
-                                    if value.len() > 0 {
-                                        f_range = Some(value)
-                                    }
-                                }
-                                "begin" => {
-                                    if value.len() > 0 {
-                                        f_begin = Some(u32::from_str(&value).unwrap())
-                                    }
-                                }
-                                "width" => {
-                                    if value.len() > 0 {
-                                        f_width = Some(u32::from_str(&value).unwrap())
-                                    }
-                                }
-                                "end" => {
-                                    if value.len() > 0 {
-                                        f_end = Some(u32::from_str(&value).unwrap())
-                                    }
-                                }
-                                "rwaccess" => {
-                                    if value.len() > 0 {
-                                        f_rwaccess = Some(value)
-                                    }
-                                }
-                                "description" => {
-                                    if value.len() > 0 {
-                                        f_description = Some(value)
-                                    }
-                                }
-                                "resetval" => {
-                                    let resetval: Result<u64, std::num::ParseIntError>;
-                                    if value.starts_with("0x") {
-                                        resetval = u64::from_str_radix(&value[2..], 16);
-                                    } else {
-                                        resetval = u64::from_str(&value);
-                                    }
-                                    f_reset_value = match resetval {
-                                        Ok(x) => Some(x),
-                                        Err(_e) => None,
-                                    };
-                                    //f_reset_value = Some(resetval.unwrap());
-                                }
-                                unknown => {
-                                    if args.verbose > 0 {
-                                        eprintln!(
-                                            "Ignoring unknown key '{}' for '{}'",
-                                            unknown, local_name
-                                        );
-                                    };
-                                }
-                            };
-                        }
-
-                        if let Some(end_int) = f_end {
-                            // Trust f_begin more than f_width
-                            if let Some(begin_int) = f_begin {
-                                f_width = Some(begin_int - end_int + 1)
-                            }
-
-                            if let Some(reset_value) = f_reset_value {
-                                let reg_width: u32 = register_width.unwrap_or(32);
-
-                                if let Some(width_int) = f_width {
-                                    if end_int + width_int > reg_width {
-                                        return Err(io::Error::new(io::ErrorKind::Other, format!("Field {:?} with offset {} and width {} too big for register of width {}.", f_name, end_int, width_int, reg_width)));
-                                    }
-                                }
-
-                                if end_int < reg_width {
-                                    let overflow = reset_value >> (reg_width - end_int);
-                                    if overflow == 0 {
-                                        let shifted_reset_value = reset_value << end_int;
-                                        if let Some(rrv) = register_reset_value {
-                                            register_reset_value = Some(rrv | shifted_reset_value)
-                                        } else {
-                                            register_reset_value = Some(shifted_reset_value);
-                                        }
-                                    } else {
-                                        if args.sanitize {
-                                            eprintln!(
-                                                "Resetval {} too big for field {:?}.",
-                                                reset_value, f_name
-                                            );
-                                        } else {
-                                            return Err(io::Error::new(
-                                                io::ErrorKind::Other,
-                                                format!(
-                                                    "Resetval {} too big for field {:?}.",
-                                                    reset_value, f_name
-                                                ),
-                                            ));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-
-                        if f_name.is_none() && args.sanitize {
-                            if let Some(description) = f_description.clone() {
-                                f_name = Some(get_name_from_description(&description));
-                            } else {
-                                let parent_reg_name = f_parent_reg_name.clone();
-                                let bit_width = f_width;
-                                let bit_offset = f_end;
-
-                                // Create the formatted string
-                                let name_value = format!(
-                                    "{}_W{}_O{}",
-                                    parent_reg_name.unwrap_or_default(),
-                                    bit_width.unwrap_or_default(),
-                                    bit_offset.unwrap_or_default()
-                                );
-                                // Assign the formatted string to f_name
-                                f_name = Some(name_value);
-                            }
-                        }
-
-                        if let Some(name) = f_name {
-                            write_tag(args, &mut xml_out, "name", &name)?;
-                        }
-                        if let Some(description) = f_description {
-                            if (f_begin != None) && (f_end != None) {
-                                let desc = format!(
-                                    "[{}:{}] {}",
-                                    f_begin.unwrap(),
-                                    f_end.unwrap(),
-                                    description
-                                );
-                                write_tag(args, &mut xml_out, "description", &desc)?;
-                            } else {
-                                write_tag(
-                                    args,
-                                    &mut xml_out,
-                                    "description",
-                                    if description.len() == 0 {
-                                        "--"
-                                    } else {
-                                        &description
-                                    },
-                                )?;
-                            }
-                        }
-
-                        if let Some(width) = f_width {
-                            write_tag(args, &mut xml_out, "bitWidth", &width.to_string())?;
-                        }
-                        if let Some(end) = f_end {
-                            write_tag(args, &mut xml_out, "bitOffset", &end.to_string())?;
-                        }
-
-                        // bitRange unlikely to work with svd2rust
-                        if !args.sanitize {
-                            if let Some(range) = f_range {
-                                write_tag(args, &mut xml_out, "bitRange", &range)?;
-                            }
-                        }
-                        if let Some(rwaccess) = f_rwaccess {
-                            write_access(args, &mut xml_out, &rwaccess)?;
-                        }
-                    }
-
-                    "bitenum" => {
-                        if !printed_enumeratedValues_tag {
-                            printed_enumeratedValues_tag = true;
-                            write_start(args, &mut xml_out, "enumeratedValues")?;
-                            if args.sanitize {
-                                f_used_enumerations = Some(HashSet::new());
-                            }
-                        }
-
-                        let mut f_id: Option<String> = None;
-                        let mut f_value: Option<String> = None;
-                        let mut f_description: Option<String> = None;
-
-                        for attr in attributes {
-                            let xml::attribute::OwnedAttribute { name, value } = attr;
-                            let value = if args.sanitize {
-                                String::from(value.trim())
-                            } else {
-                                value
-                            };
-                            let OwnedName {
-                                local_name: attr_name,
-                                ..
-                            } = name;
-                            match attr_name.as_ref() {
-                                "id" => {
-                                    if value.len() > 0 {
-                                        f_id = Some(value)
-                                    }
-                                }
-                                "value" => {
-                                    if value.len() > 0 {
-                                        f_value = Some(value)
-                                    }
-                                }
-                                "description" => {
-                                    if value.len() > 0 {
-                                        f_description = Some(value)
-                                    }
-                                }
-                                "token" => (),
-                                unknown => {
-                                    if args.verbose > 0 {
-                                        eprintln!(
-                                            "Ignoring unknown key '{}' for '{}'",
-                                            unknown, local_name
-                                        );
-                                    };
-                                }
-                            };
-                        }
-
-                        if let Some(value) = f_value {
-                            let do_it: bool = match f_used_enumerations {
-                                Some(ref mut used_enumerations) => {
-                                    used_enumerations.insert(value.clone())
-                                }
-                                None => true,
-                            };
-                            if do_it {
-                                write_start(args, &mut xml_out, "enumeratedValue")?;
-                                if let Some(id) = f_id {
-                                    write_tag(args, &mut xml_out, "name", &id)?;
-                                } else {
-                                    if args.sanitize {
-                                        // If id is missing, use value instead
-                                        write_tag(args, &mut xml_out, "name", &value)?;
-                                    }
-                                }
-                                write_tag(args, &mut xml_out, "value", &value)?;
-                                if let Some(description) = f_description {
-                                    write_tag(
-                                        args,
-                                        &mut xml_out,
-                                        "description",
-                                        if description.len() == 0 {
-                                            "--"
-                                        } else {
-                                            &description
-                                        },
-                                    )?;
-                                }
-                                write_end(args, &mut xml_out)?;
-                            } else {
-                                eprintln!("Non-unique enumeration name {}. Ignoring.", value);
-                            }
-                        }
-                    }
-                    unknown => {
-                        if args.verbose > 0 {
-                            eprintln!("Ignoring unknown start element key '{}'", unknown);
-                        }
-                    }
-                };
-            }
-            Ok(EndElement { name }) => {
-                if args.verbose > 0 {
-                    eprintln!("Processing EndElement: {}", name);
-                }
-                let OwnedName {
-                    local_name,
-                    prefix: _,
-                    namespace: _,
-                } = name;
-                match local_name.as_ref() {
-                    "module" => {
-                        f_used_registers = None;
-
-                        if printed_registers_tag {
-                            printed_registers_tag = false;
-                            write_end(args, &mut xml_out)?;
-                        }
-                        if args.peripheral_only {
-                            write_end(args, &mut xml_out)?;
-                        }
-                    }
-
-                    "register" => {
-                        if printed_fields_tag {
-                            printed_fields_tag = false;
-                            write_end(args, &mut xml_out)?;
-                        }
-
-                        if let Some(value) = register_reset_value {
-                            let hex_reset = format!("0x{:X}", value);
-                            write_tag(args, &mut xml_out, "resetValue", &hex_reset)?;
-                        } else {
-                            // For svd2rust
-                            let rv = "0";
-                            write_tag(args, &mut xml_out, "resetValue", &rv)?;
-                        }
-
-                        register_width = None;
-                        write_end(args, &mut xml_out)?;
-                    }
-
-                    "bitfield" => {
-                        if printed_enumeratedValues_tag {
-                            printed_enumeratedValues_tag = false;
-                            write_end(args, &mut xml_out)?;
-                            f_used_enumerations = None;
-                        }
-                        write_end(args, &mut xml_out)?;
-                    }
-
-                    "bitenum" => {}
-                    unknown => {
-                        if args.verbose > 0 {
-                            eprintln!("Ignoring unknown end element key '{}'", unknown);
-                        }
-                    }
-                };
-            }
-            Err(e) => {
-                return Err(io::Error::new(io::ErrorKind::Other, e.to_string()));
-            }
-            _ => {}
-        }
+    let mut peripheral = read_peripheral(args, parser)?;
+    // Without a device, registers have the default size of one
+    check_field_sizes(&peripheral, Device::default().size)?;
+    if args.dim_arrays {
+        make_register_arrays(&mut peripheral);
     }
-    Ok(())
+    finish_peripheral(args, &mut peripheral);
+    write_peripheral(args, &peripheral, xml_out)
 }
@@ -3,34 +3,87 @@ extern crate xml;
 
 extern crate tixml2svd;
 
-use tixml2svd::{process_device, process_peripheral, Args};
+use tixml2svd::{
+    diff_devices, diff_module_dirs, inspect_device, load_device, open_tixml_file,
+    process_device_cpus, process_devices_dir, process_peripheral, read_cpus, read_interrupt_file,
+    read_patch_file, write_batch_summary, write_c_header, write_diff, write_docs,
+    write_inventory_json, write_inventory_table, write_json, write_svd, write_yaml, Args,
+    DocFormat, EnumDuplicates, Error,
+};
 
-use std::fs::File;
-use std::io::{Error, ErrorKind, Seek, SeekFrom};
-use unicode_bom::Bom;
+use std::fs;
+use std::path::Path;
+
+use xml::reader::EventReader;
 
 fn main() {
     ::std::process::exit(match main_() {
         Ok(_) => 0,
         Err(err) => {
-            eprintln!("error: {:?}", err);
+            eprintln!("error: {}", err);
             1
         }
     });
 }
 
-fn main_() -> std::io::Result<()> {
-    let matches = clap::App::new("tixml2svd")
+fn main_() -> Result<(), Box<dyn std::error::Error>> {
+    let app = clap::App::new("tixml2svd")
         .version("0.1")
         .about("Convert Texas-Instruments device xml data into SVD format.")
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            clap::SubCommand::with_name("diff")
+                .about("Compare two device files, or two Modules directories")
+                .arg(
+                    clap::Arg::with_name("OLD")
+                        .required(true)
+                        .help("Old device xml file or Modules directory"),
+                )
+                .arg(
+                    clap::Arg::with_name("NEW")
+                        .required(true)
+                        .help("New device xml file or Modules directory"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("inspect")
+                .alias("list")
+                .about("List the cpus and peripheral instances of a device file")
+                .arg(
+                    clap::Arg::with_name("FILE")
+                        .required(true)
+                        .help("Device xml file"),
+                )
+                .arg(
+                    clap::Arg::with_name("json")
+                        .long("json")
+                        .help("Print JSON instead of a table"),
+                ),
+        )
         .arg(
             clap::Arg::with_name("input")
                 .short("i")
                 .long("input")
                 .value_name("FILE")
-                .required(true)
+                .required_unless("batch")
                 .help("Input xml file"),
         )
+        .arg(
+            clap::Arg::with_name("batch")
+                .short("b")
+                .long("batch")
+                .value_name("DIR")
+                .conflicts_with_all(&["input", "peripheral"])
+                .requires("output")
+                .help("Convert all device files in a targetdb devices directory"),
+        )
+        .arg(
+            clap::Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("DIR")
+                .help("Output directory for batch conversion or --all-cpus"),
+        )
         .arg(
             clap::Arg::with_name("header")
                 .short("h")
@@ -39,6 +92,46 @@ fn main_() -> std::io::Result<()> {
                 .required(false)
                 .help("Optional device header filename"),
         )
+        .arg(
+            clap::Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["svd", "json", "yaml"])
+                .conflicts_with_all(&["batch", "peripheral", "all_cpus"])
+                .help("Output format, with absolute register addresses in json and yaml [default: svd]"),
+        )
+        .arg(
+            clap::Arg::with_name("docs")
+                .long("docs")
+                .value_name("DIR")
+                .conflicts_with_all(&["batch", "peripheral", "all_cpus"])
+                .help("Also write register reference pages into a directory"),
+        )
+        .arg(
+            clap::Arg::with_name("docs_format")
+                .long("docs-format")
+                .value_name("FORMAT")
+                .possible_values(&["markdown", "html"])
+                .requires("docs")
+                .help("Format of the register reference pages [default: markdown]"),
+        )
+        .arg(
+            clap::Arg::with_name("c_header")
+                .long("c-header")
+                .value_name("FILE")
+                .conflicts_with_all(&["batch", "peripheral"])
+                .help("Also write a CMSIS-style C header file"),
+        )
+        .arg(
+            clap::Arg::with_name("interrupts")
+                .short("I")
+                .long("interrupts")
+                .value_name("FILE")
+                .conflicts_with_all(&["batch", "peripheral"])
+                .help(
+                    "Read interrupts from a C header with an IRQn_Type enum, a TOML or a CSV file",
+                ),
+        )
         .arg(
             clap::Arg::with_name("cpunum")
                 .short("c")
@@ -46,6 +139,19 @@ fn main_() -> std::io::Result<()> {
                 .value_name("INTEGER")
                 .help("Select cpu number with an integer, starting with 0"),
         )
+        .arg(
+            clap::Arg::with_name("all_cpus")
+                .long("all-cpus")
+                .conflicts_with_all(&["cpunum", "peripheral", "c_header"])
+                .requires("output")
+                .help("Write one SVD file per cpu into the output directory"),
+        )
+        .arg(
+            clap::Arg::with_name("list_cpus")
+                .long("list-cpus")
+                .conflicts_with_all(&["batch", "peripheral"])
+                .help("List the cpus of the input device file and exit"),
+        )
         .arg(
             clap::Arg::with_name("peripheral")
                 .short("p")
@@ -58,6 +164,52 @@ fn main_() -> std::io::Result<()> {
                 .long("sanitize")
                 .help("Sanitize file for code generation or picky postprocessors"),
         )
+        .arg(
+            clap::Arg::with_name("enum_duplicates")
+                .long("enum-duplicates")
+                .value_name("POLICY")
+                .possible_values(&["drop", "merge", "rename", "alias", "split"])
+                .help("With -z, what to do with enumerated values whose value is already used [default: drop]"),
+        )
+        .arg(
+            clap::Arg::with_name("arrays")
+                .short("a")
+                .long("arrays")
+                .help("Combine repeated registers into register arrays"),
+        )
+        .arg(
+            clap::Arg::with_name("derive_enums")
+                .long("derive-enums")
+                .help("Write identical enumerated values once and derive the others from them"),
+        )
+        .arg(
+            clap::Arg::with_name("group")
+                .short("g")
+                .long("group")
+                .value_name("PATTERN=GROUP")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Put peripherals from module files starting with PATTERN into GROUP"),
+        )
+        .arg(
+            clap::Arg::with_name("patch")
+                .long("patch")
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("peripheral")
+                .help("Apply the fixes of a YAML or TOML patch file to each device"),
+        )
+        .arg(
+            clap::Arg::with_name("no_builtin_patches")
+                .long("no-builtin-patches")
+                .help("Do not apply the built-in fixes for the CCSv8 device files"),
+        )
+        .arg(
+            clap::Arg::with_name("validate")
+                .long("validate")
+                .help("Warn about overlapping registers, fields too big and similar problems"),
+        )
         .arg(
             clap::Arg::with_name("no_device_info")
                 .short("x")
@@ -76,65 +228,192 @@ fn main_() -> std::io::Result<()> {
                 .short("s")
                 .long("silent")
                 .help("Be silent"),
-        )
-        .get_matches();
+        );
+    #[cfg(feature = "pac")]
+    let app = app.arg(
+        clap::Arg::with_name("pac")
+            .long("pac")
+            .value_name("DIR")
+            .conflicts_with_all(&["batch", "peripheral", "all_cpus"])
+            .help("Also write a Rust peripheral access crate, implies --sanitize"),
+    );
+    let matches = app.get_matches();
 
-    let fname_in = matches.value_of("input").unwrap();
+    if let Some(diff) = matches.subcommand_matches("diff") {
+        let old = diff.value_of("OLD").unwrap();
+        let new = diff.value_of("NEW").unwrap();
+        let args = Args::new(true, 0, false, false, false, 0);
+        let entries = if Path::new(old).is_dir() && Path::new(new).is_dir() {
+            diff_module_dirs(&args, Path::new(old), Path::new(new))?
+        } else {
+            let old_device = load_device(&args, open_tixml_file(old)?, old, None)?;
+            let new_device = load_device(&args, open_tixml_file(new)?, new, None)?;
+            diff_devices(&old_device, &new_device)
+        };
+        write_diff(&entries, &mut std::io::stdout().lock())?;
+        for warning in args.take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+        return Ok(());
+    }
+
+    if let Some(inspect) = matches.subcommand_matches("inspect") {
+        let fname_in = inspect.value_of("FILE").unwrap();
+        let args = Args::new(true, 0, false, false, false, 0);
+        let parser = EventReader::new(open_tixml_file(fname_in)?);
+        let inventory = inspect_device(&args, parser, fname_in).map_err(|e| e.in_file(fname_in))?;
+        let stdout = std::io::stdout();
+        if inspect.is_present("json") {
+            write_inventory_json(&inventory, &mut stdout.lock())?;
+        } else {
+            write_inventory_table(&inventory, &mut stdout.lock())?;
+        }
+        return Ok(());
+    }
 
     let requested_cpunum = matches
         .value_of("cpunum")
         .unwrap_or("0")
         .parse::<u32>()
-        .map_err(|_| {
-            Error::new(
-                ErrorKind::Other,
-                format!("invalid cpunum, must be a valid non-negative integer."),
-            )
-        })?;
+        .map_err(|_| "invalid cpunum, must be a valid non-negative integer.")?;
+
+    let mut group_map = vec![];
+    for group in matches.values_of("group").into_iter().flatten() {
+        match group.split_once('=') {
+            Some((pattern, name)) => group_map.push((pattern.to_string(), name.to_string())),
+            None => return Err(format!("invalid group '{}', must be PATTERN=GROUP", group).into()),
+        }
+    }
+
+    let interrupts = match matches.value_of("interrupts") {
+        Some(interrupt_filename) => read_interrupt_file(interrupt_filename)?,
+        None => vec![],
+    };
+
+    let patches = matches
+        .values_of("patch")
+        .into_iter()
+        .flatten()
+        .map(read_patch_file)
+        .collect::<tixml2svd::Result<Vec<_>>>()?;
 
     let args = Args::new(
         matches.is_present("silent"),
         matches.occurrences_of("verbose") as u32,
         matches.is_present("peripheral"),
-        matches.is_present("sanitize"),
+        matches.is_present("sanitize") || matches.is_present("pac"),
         matches.is_present("no_device_info"),
         requested_cpunum,
-    );
+    )
+    .with_dim_arrays(matches.is_present("arrays"))
+    .with_derive_enums(matches.is_present("derive_enums"))
+    .with_group_map(group_map)
+    .with_interrupts(interrupts)
+    .with_builtin_patches(!matches.is_present("no_builtin_patches"))
+    .with_patches(patches)
+    .with_all_cpus(matches.is_present("all_cpus"))
+    .with_validate(matches.is_present("validate"))
+    .with_enum_duplicates(match matches.value_of("enum_duplicates") {
+        Some("merge") => EnumDuplicates::Merge,
+        Some("rename") => EnumDuplicates::Rename,
+        Some("alias") => EnumDuplicates::Alias,
+        Some("split") => EnumDuplicates::Split,
+        _ => EnumDuplicates::Drop,
+    });
+
+    let device_header_str = match matches.value_of("header") {
+        Some(device_header_filename) => Some(
+            fs::read_to_string(device_header_filename)
+                .map_err(|e| Error::from(e).in_file(device_header_filename))?,
+        ),
+        None => None,
+    };
+    let device_header = device_header_str.as_deref();
+
+    if let Some(fname_in) = matches
+        .value_of("input")
+        .filter(|_| matches.is_present("list_cpus"))
+    {
+        let cpus = read_cpus(&args, EventReader::new(open_tixml_file(fname_in)?))
+            .map_err(|e| e.in_file(fname_in))?;
+        println!("Index  {:<16}  {:<12}  Description", "Id", "ISA");
+        for cpu in cpus {
+            println!(
+                "{:<5}  {:<16}  {:<12}  {}",
+                cpu.index, cpu.id, cpu.isa, cpu.description
+            );
+        }
+        return Ok(());
+    }
+
+    let results = match (matches.value_of("batch"), matches.value_of("output")) {
+        (Some(devices_dir), Some(out_dir)) => Some(process_devices_dir(
+            &args,
+            Path::new(devices_dir),
+            Path::new(out_dir),
+            device_header,
+        )?),
+        (None, Some(out_dir)) if matches.is_present("all_cpus") => Some(process_device_cpus(
+            &args,
+            Path::new(matches.value_of("input").unwrap()),
+            Path::new(out_dir),
+            device_header,
+        )?),
+        _ => None,
+    };
+    if let Some(results) = results {
+        write_batch_summary(&results, &mut std::io::stdout().lock())?;
+
+        let failed = results.iter().filter(|r| r.result.is_err()).count();
+        if failed > 0 {
+            return Err(format!("{} conversions failed", failed).into());
+        }
+        return Ok(());
+    }
+
+    let fname_in = matches.value_of("input").unwrap();
 
     if !matches.is_present("silent") {
         eprintln!("Processing file: {}", fname_in);
     }
 
-    let mut fd_in = File::open(fname_in)?;
-
-    // Some CCXML files contain unicode BOMs; these must be read to avoid
-    // XML parse errors.
-    let bom = Bom::from(&mut fd_in);
-    match bom {
-        Bom::Null | Bom::Utf8 => fd_in.seek(SeekFrom::Start(bom.len() as u64))?,
-        _ => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("unsupported Unicode file encoding: {}", bom),
-            ))
-        }
-    };
+    let fd_in = open_tixml_file(fname_in)?;
 
     let stdout = std::io::stdout();
     let mut fd_out = stdout.lock();
 
-    if matches.is_present("peripheral") {
-        process_peripheral(&args, fd_in, &mut fd_out)
+    let result = if matches.is_present("peripheral") {
+        process_peripheral(&args, fd_in, &mut fd_out).map_err(|e| e.in_file(fname_in))
     } else {
-        /*
-        let mut device_header_str = String::new();
-        let mut device_header = None;
-        if let Some(device_header_filename) = matches.value_of("header") {
-            let mut device_header_file = File::open(device_header_filename)?;
-            device_header_file.read_to_string(&mut device_header_str)?;
-            device_header = Some(&device_header_str[..]);
+        load_device(&args, fd_in, fname_in, device_header).and_then(|device| {
+            match matches.value_of("format") {
+                Some("json") => write_json(&args, &device, &mut fd_out)?,
+                Some("yaml") => write_yaml(&args, &device, &mut fd_out)?,
+                _ => write_svd(&args, &device, &mut fd_out)?,
+            }
+            if let Some(docs_dir) = matches.value_of("docs") {
+                let format = match matches.value_of("docs_format") {
+                    Some("html") => DocFormat::Html,
+                    _ => DocFormat::Markdown,
+                };
+                write_docs(&args, &device, Path::new(docs_dir), format)?;
+            }
+            if let Some(fname_c) = matches.value_of("c_header") {
+                let mut fd_c =
+                    fs::File::create(fname_c).map_err(|e| Error::from(e).in_file(fname_c))?;
+                write_c_header(&args, &device, &mut fd_c).map_err(|e| e.in_file(fname_c))?;
+            }
+            #[cfg(feature = "pac")]
+            if let Some(pac_dir) = matches.value_of("pac") {
+                tixml2svd::write_pac(&args, &device, Path::new(pac_dir))?;
+            }
+            Ok(())
+        })
+    };
+    if !matches.is_present("silent") {
+        for warning in args.take_warnings() {
+            eprintln!("warning: {}", warning.in_file(fname_in));
         }
-         */
-        process_device(&args, fd_in, &fname_in, &mut fd_out)
     }
+    Ok(result?)
 }
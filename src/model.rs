@@ -0,0 +1,306 @@
+//! In-memory representation of a converted device.
+//!
+//! The TIXML reader fills in these structures and the SVD writer
+//! serializes them, so a device can be inspected or modified in between.
+
+/// Access rights of a register or field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+impl Access {
+    /// The SVD spelling of this access type.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::ReadOnly => "read-only",
+            Access::WriteOnly => "write-only",
+            Access::ReadWrite => "read-write",
+        }
+    }
+
+    /// Parse the SVD spelling of an access type.
+    pub fn from_svd(access: &str) -> Option<Access> {
+        match access {
+            "read-only" => Some(Access::ReadOnly),
+            "write-only" => Some(Access::WriteOnly),
+            "read-write" => Some(Access::ReadWrite),
+            _ => None,
+        }
+    }
+}
+
+/// What writing a register or field does to its bits, the SVD
+/// `<modifiedWriteValues>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifiedWriteValues {
+    OneToClear,
+    OneToSet,
+    OneToToggle,
+    ZeroToClear,
+    ZeroToSet,
+    ZeroToToggle,
+    Clear,
+    Set,
+    Modify,
+}
+
+impl ModifiedWriteValues {
+    /// The SVD spelling of this write side effect.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModifiedWriteValues::OneToClear => "oneToClear",
+            ModifiedWriteValues::OneToSet => "oneToSet",
+            ModifiedWriteValues::OneToToggle => "oneToToggle",
+            ModifiedWriteValues::ZeroToClear => "zeroToClear",
+            ModifiedWriteValues::ZeroToSet => "zeroToSet",
+            ModifiedWriteValues::ZeroToToggle => "zeroToToggle",
+            ModifiedWriteValues::Clear => "clear",
+            ModifiedWriteValues::Set => "set",
+            ModifiedWriteValues::Modify => "modify",
+        }
+    }
+}
+
+/// What reading a register or field does to its bits, the SVD
+/// `<readAction>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadAction {
+    Clear,
+    Set,
+    Modify,
+}
+
+impl ReadAction {
+    /// The SVD spelling of this read side effect.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadAction::Clear => "clear",
+            ReadAction::Set => "set",
+            ReadAction::Modify => "modify",
+        }
+    }
+}
+
+/// Whether enumerated values are for reading, writing or both, the SVD
+/// `<usage>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Usage {
+    /// The SVD spelling of this usage.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Usage::Read => "read",
+            Usage::Write => "write",
+            Usage::ReadWrite => "read-write",
+        }
+    }
+
+    /// The usage of the enumerated values of a field with this access.
+    pub fn from_access(access: Access) -> Usage {
+        match access {
+            Access::ReadOnly => Usage::Read,
+            Access::WriteOnly => Usage::Write,
+            Access::ReadWrite => Usage::ReadWrite,
+        }
+    }
+}
+
+/// A complete device, corresponding to the SVD `<device>` element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub cpu: Cpu,
+    pub address_unit_bits: u32,
+    pub width: u32,
+    /// Default register size
+    pub size: u32,
+    /// Default register access
+    pub access: Access,
+    /// Default register reset value
+    pub reset_value: u64,
+    /// Default register reset mask
+    pub reset_mask: u64,
+    pub peripherals: Vec<Peripheral>,
+}
+
+impl Default for Device {
+    fn default() -> Device {
+        Device {
+            name: "[unknown CPU]".to_string(),
+            version: "0.0".to_string(),
+            description: String::new(),
+            cpu: Cpu::default(),
+            address_unit_bits: 8,
+            width: 32,
+            size: 32,
+            access: Access::ReadWrite,
+            reset_value: 0,
+            reset_mask: 0xFFFF_FFFF,
+            peripherals: vec![],
+        }
+    }
+}
+
+/// The SVD `<cpu>` element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cpu {
+    pub name: String,
+    pub revision: String,
+    pub endian: String,
+    pub mpu_present: bool,
+    pub fpu_present: bool,
+    pub nvic_prio_bits: u32,
+    pub vendor_systick_config: bool,
+}
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu {
+            name: "other".to_string(),
+            revision: "0.0".to_string(),
+            endian: "other".to_string(),
+            mpu_present: true,
+            fpu_present: true,
+            nvic_prio_bits: 3,
+            vendor_systick_config: false,
+        }
+    }
+}
+
+/// A peripheral, read from a TIXML module file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Peripheral {
+    pub name: String,
+    /// Name of an identical peripheral. The registers are still filled
+    /// in, but only the name and base address are written to SVD.
+    pub derived_from: Option<String>,
+    /// TIXML `value` attribute; not part of SVD, but written verbatim.
+    pub value: Option<String>,
+    pub description: Option<String>,
+    /// SVD `<groupName>`, derived from the module file name
+    pub group_name: Option<String>,
+    /// TIXML module file, such as `msp430/RTC_C_208.0`; not part of SVD
+    pub module: Option<String>,
+    pub base_address: Option<u64>,
+    /// Size of the register address block, in address units
+    pub size: Option<u64>,
+    pub interrupts: Vec<Interrupt>,
+    pub registers: Vec<Register>,
+}
+
+/// An interrupt of a peripheral. TIXML does not describe interrupts, so
+/// these come from a separate file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Interrupt {
+    pub name: String,
+    pub description: Option<String>,
+    /// Interrupt number, starting at 0 for the first device interrupt
+    pub value: u32,
+}
+
+/// A register of a peripheral.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Register {
+    /// Set if this register stands for an array of registers
+    pub dim: Option<Dim>,
+    pub name: String,
+    /// TIXML `value` attribute; not part of SVD, but written verbatim.
+    pub value: Option<String>,
+    pub description: Option<String>,
+    pub address_offset: u64,
+    /// Register width in bits
+    pub size: Option<u32>,
+    pub access: Option<Access>,
+    pub modified_write_values: Option<ModifiedWriteValues>,
+    pub read_action: Option<ReadAction>,
+    /// Reset value, combined from the register and its fields
+    pub reset_value: Option<u64>,
+    pub fields: Vec<Field>,
+}
+
+/// SVD array information. Each `%s` in the name and description of the
+/// element is replaced by one of the indices.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dim {
+    /// Number of elements
+    pub dim: u32,
+    /// Address distance between elements, in address units
+    pub dim_increment: u64,
+    /// The indices, either as a range such as `0-3` or comma separated
+    pub dim_index: Option<String>,
+}
+
+impl Dim {
+    /// The index of each element, `0` to `dim - 1` unless given in
+    /// `dim_index`.
+    pub fn indices(&self) -> Vec<String> {
+        let range = |first: u32, last: u32| (first..=last).map(|i| i.to_string()).collect();
+        match self.dim_index {
+            Some(ref index) => match index.split_once('-') {
+                Some((first, last)) => match (first.parse(), last.parse()) {
+                    (Ok(first), Ok(last)) => range(first, last),
+                    _ => index.split(',').map(|i| i.trim().to_string()).collect(),
+                },
+                None => index.split(',').map(|i| i.trim().to_string()).collect(),
+            },
+            None if self.dim > 0 => range(0, self.dim - 1),
+            None => vec![],
+        }
+    }
+}
+
+/// A bit field of a register.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: Option<u32>,
+    pub bit_width: Option<u32>,
+    /// TIXML `range` attribute, written as `<bitRange>` if present
+    pub bit_range: Option<String>,
+    pub access: Option<Access>,
+    pub modified_write_values: Option<ModifiedWriteValues>,
+    pub read_action: Option<ReadAction>,
+    /// Name of the `<enumeratedValues>`, given when other fields derive
+    /// from them
+    pub enumerated_values_name: Option<String>,
+    /// Path of the identical enumerated values of another field, such as
+    /// `CTL.MODE.MODE`. The values are still filled in, but only the path
+    /// is written to SVD.
+    pub enumerated_values_derived_from: Option<String>,
+    pub enumerated_values: Vec<EnumeratedValue>,
+    /// Values for writing, if they differ from those for reading; the
+    /// `enumerated_values` are then only for reading
+    pub write_enumerated_values: Vec<EnumeratedValue>,
+}
+
+impl Field {
+    /// The enumerated values for reading, followed by those for writing
+    /// that differ from them.
+    pub fn all_enumerated_values(&self) -> impl Iterator<Item = &EnumeratedValue> {
+        let read = &self.enumerated_values;
+        read.iter().chain(
+            self.write_enumerated_values
+                .iter()
+                .filter(move |value| !read.contains(value)),
+        )
+    }
+}
+
+/// One value of a field enumeration.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnumeratedValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub value: String,
+}
@@ -0,0 +1,1094 @@
+//! Writing the device model as a Rust peripheral access crate.
+//!
+//! The crate has the layout and register API of an svd2rust generated
+//! crate: a `Peripherals` singleton, one struct per peripheral that
+//! dereferences to its `RegisterBlock`, and `read`, `write` and `modify`
+//! methods on each register with a reader and writer per field. Cortex-M
+//! devices also get an `Interrupt` enum and, with the `rt` feature of the
+//! crate, a vector table for cortex-m-rt.
+//!
+//! Enumerated values that do not fit into their field, and names that
+//! would clash in Rust, are left out with a warning instead of producing
+//! a crate that does not build.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result, Warning};
+use crate::model::{
+    Access, Device, EnumeratedValue, Field, ModifiedWriteValues, Peripheral, Register,
+};
+use crate::reader::parse_number;
+use crate::Args;
+
+/// The generic register API, the same for all crates.
+const GENERIC: &str = include_str!("../templates/pac_generic.rs");
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Methods of the generic readers and writers, which field and value
+/// methods must not hide.
+const RESERVED: &[&str] = &[
+    "bit",
+    "bit_is_clear",
+    "bit_is_set",
+    "bits",
+    "clear_bit",
+    "new",
+    "offset",
+    "set_bit",
+    "variant",
+    "width",
+];
+
+/// Make a Rust identifier from an SVD name, dropping array placeholders.
+fn ident(name: &str) -> String {
+    let mut ident: String = name
+        .replace("%s", "")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// A type name, such as `GPIO`.
+fn upper_name(name: &str) -> String {
+    ident(name).to_uppercase()
+}
+
+/// A module or method name, such as `gpio`.
+fn snake_name(name: &str) -> String {
+    let mut snake = ident(name).to_lowercase();
+    if KEYWORDS.contains(&snake.as_str()) || RESERVED.contains(&snake.as_str()) {
+        snake.push('_');
+    }
+    snake
+}
+
+/// A `#[doc]` attribute holding a TIXML description.
+fn doc(text: &str) -> String {
+    let text = text
+        .replace("#br#", " ")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    format!("#[doc = {:?}]", text)
+}
+
+/// The smallest unsigned type holding `bits` bits.
+fn rust_type(bits: u32) -> (&'static str, u64) {
+    match bits {
+        0..=8 => ("u8", 1),
+        9..=16 => ("u16", 2),
+        17..=32 => ("u32", 4),
+        _ => ("u64", 8),
+    }
+}
+
+fn mask(width: u32) -> u64 {
+    u64::MAX >> (64 - width.clamp(1, 64))
+}
+
+fn duplicate_name(name: &str) -> Warning {
+    Warning::DuplicateName {
+        context: Box::default(),
+        name: name.to_string(),
+    }
+}
+
+/// The enumerated values of a field that can be written as Rust enum
+/// variants, with their numbers.
+fn enum_variants<'a>(
+    args: &Args,
+    enumerated_values: &'a [EnumeratedValue],
+    width: u32,
+) -> Vec<(String, u64, &'a EnumeratedValue)> {
+    let mut variants = vec![];
+    let mut names = HashSet::new();
+    let mut values = HashSet::new();
+    for value in enumerated_values {
+        let number = match parse_number("value", &value.value) {
+            Ok(number) if width < 64 && number >> width != 0 => {
+                args.warn(Warning::EnumValueTooBig {
+                    context: Box::default(),
+                    value: value.value.clone(),
+                    bit_width: width,
+                });
+                continue;
+            }
+            Ok(number) => number,
+            Err(e) => {
+                args.warn(e);
+                continue;
+            }
+        };
+        let name = upper_name(&value.name);
+        if !values.insert(number) {
+            args.warn(Warning::DuplicateEnumValue {
+                context: Box::default(),
+                value: value.value.clone(),
+            });
+        } else if !names.insert(name.clone()) {
+            args.warn(duplicate_name(&value.name));
+        } else {
+            variants.push((name, number, value));
+        }
+    }
+    variants
+}
+
+/// Define the enum of the enumerated values of a field, if it has any.
+fn write_enum(
+    defs: &mut String,
+    description: &str,
+    enum_name: &str,
+    ux: &str,
+    bit: bool,
+    variants: &[(String, u64, &EnumeratedValue)],
+) -> std::fmt::Result {
+    if variants.is_empty() {
+        return Ok(());
+    }
+    writeln!(defs, "{}", doc(description))?;
+    writeln!(defs, "#[derive(Clone, Copy, Debug, PartialEq, Eq)]")?;
+    writeln!(defs, "#[repr({})]", ux)?;
+    writeln!(defs, "pub enum {} {{", enum_name)?;
+    for (name, number, value) in variants {
+        let description = value.description.as_deref().unwrap_or(&value.name);
+        writeln!(defs, "    {}", doc(&format!("{}: {}", number, description)))?;
+        writeln!(defs, "    {} = {},", name, number)?;
+    }
+    writeln!(defs, "}}")?;
+    if bit {
+        writeln!(defs, "impl From<{}> for bool {{", enum_name)?;
+        writeln!(defs, "    #[inline(always)]")?;
+        writeln!(defs, "    fn from(variant: {}) -> Self {{", enum_name)?;
+        writeln!(defs, "        variant as u8 != 0")?;
+        writeln!(defs, "    }}")?;
+        writeln!(defs, "}}")
+    } else {
+        writeln!(defs, "impl From<{}> for {} {{", enum_name, ux)?;
+        writeln!(defs, "    #[inline(always)]")?;
+        writeln!(defs, "    fn from(variant: {}) -> Self {{", enum_name)?;
+        writeln!(defs, "        variant as _")?;
+        writeln!(defs, "    }}")?;
+        writeln!(defs, "}}")?;
+        writeln!(defs, "impl crate::FieldSpec for {} {{", enum_name)?;
+        writeln!(defs, "    type Ux = {};", ux)?;
+        writeln!(defs, "}}")
+    }
+}
+
+/// Code for the fields of a register: the reader, writer and enum
+/// definitions, and the methods of the register reader and writer.
+#[derive(Default)]
+struct FieldCode {
+    /// Name of the register type, such as `CTL_SPEC`
+    spec: String,
+    register_width: u32,
+    definitions: String,
+    read_methods: String,
+    write_methods: String,
+}
+
+fn write_field(
+    args: &Args,
+    field: &Field,
+    offset: u32,
+    width: u32,
+    access: Access,
+    code: &mut FieldCode,
+) -> std::fmt::Result {
+    let upper = upper_name(&field.name);
+    let snake = snake_name(&field.name);
+    let description = field.description.as_deref().unwrap_or(&field.name);
+    let (ux, _) = rust_type(width);
+    let bit = width == 1;
+    let readable = access != Access::WriteOnly;
+    let writable = access != Access::ReadOnly;
+    let enum_name = format!("{}_A", upper);
+    let variants = enum_variants(args, &field.enumerated_values, width);
+    write_enum(
+        &mut code.definitions,
+        description,
+        &enum_name,
+        ux,
+        bit,
+        &variants,
+    )?;
+    // Separate values for writing get their own enum, as in svd2rust
+    let (write_enum_name, write_variants) = if field.write_enumerated_values.is_empty() {
+        (enum_name.clone(), variants.clone())
+    } else {
+        let write_enum_name = format!("{}_AW", upper);
+        let write_variants = enum_variants(args, &field.write_enumerated_values, width);
+        write_enum(
+            &mut code.definitions,
+            description,
+            &write_enum_name,
+            ux,
+            bit,
+            &write_variants,
+        )?;
+        (write_enum_name, write_variants)
+    };
+    let complete = |variants: &[(String, u64, &EnumeratedValue)]| {
+        width < 64 && variants.len() as u64 == 1 << width
+    };
+    let defs = &mut code.definitions;
+
+    let bits = if width == 1 {
+        format!("Bit {}", offset)
+    } else {
+        format!("Bits {}:{}", offset, offset + width - 1)
+    };
+    let shifted = if offset == 0 {
+        "self.bits".to_string()
+    } else {
+        format!("(self.bits >> {})", offset)
+    };
+
+    if readable {
+        let complete = complete(&variants);
+        let reader = format!("{}_R", upper);
+        writeln!(
+            defs,
+            "{}",
+            doc(&format!("Field `{}` reader - {}", field.name, description))
+        )?;
+        let reader_type = match (bit, variants.is_empty(), ux) {
+            (true, true, _) => "crate::BitReader".to_string(),
+            (true, false, _) => format!("crate::BitReader<{}>", enum_name),
+            (false, true, "u8") => "crate::FieldReader".to_string(),
+            (false, true, ux) => format!("crate::FieldReader<{}>", ux),
+            (false, false, _) => format!("crate::FieldReader<{}>", enum_name),
+        };
+        writeln!(defs, "pub type {} = {};", reader, reader_type)?;
+
+        if !variants.is_empty() {
+            writeln!(defs, "impl {} {{", reader)?;
+            writeln!(defs, "    #[doc = \"Get enumerated values variant\"]")?;
+            writeln!(defs, "    #[inline(always)]")?;
+            let result = if complete {
+                enum_name.clone()
+            } else {
+                format!("Option<{}>", enum_name)
+            };
+            writeln!(defs, "    pub const fn variant(&self) -> {} {{", result)?;
+            writeln!(defs, "        match self.bits {{")?;
+            for (name, number, _) in &variants {
+                let pattern = if bit {
+                    (*number != 0).to_string()
+                } else {
+                    number.to_string()
+                };
+                let variant = format!("{}::{}", enum_name, name);
+                let variant = if complete {
+                    variant
+                } else {
+                    format!("Some({})", variant)
+                };
+                writeln!(defs, "            {} => {},", pattern, variant)?;
+            }
+            match (complete, bit) {
+                (true, true) => {}
+                (true, false) => writeln!(defs, "            _ => unreachable!(),")?,
+                (false, _) => writeln!(defs, "            _ => None,")?,
+            }
+            writeln!(defs, "        }}")?;
+            writeln!(defs, "    }}")?;
+            for (name, _, value) in &variants {
+                let description = value.description.as_deref().unwrap_or(&value.name);
+                writeln!(defs, "    {}", doc(description))?;
+                writeln!(defs, "    #[inline(always)]")?;
+                writeln!(
+                    defs,
+                    "    pub fn is_{}(&self) -> bool {{",
+                    ident(name).to_lowercase()
+                )?;
+                writeln!(defs, "        *self == {}::{}", enum_name, name)?;
+                writeln!(defs, "    }}")?;
+            }
+            writeln!(defs, "}}")?;
+        }
+
+        let top = offset + width == code.register_width;
+        let value = match (bit, top, ux == rust_type(code.register_width).0) {
+            (true, _, _) => format!("({} & 1) != 0", shifted),
+            (false, true, true) => shifted,
+            (false, true, false) => format!("{} as {}", shifted, ux),
+            (false, false, true) => format!("{} & 0x{:x}", shifted, mask(width)),
+            (false, false, false) => format!("({} & 0x{:x}) as {}", shifted, mask(width), ux),
+        };
+        let methods = &mut code.read_methods;
+        writeln!(
+            methods,
+            "    {}",
+            doc(&format!("{} - {}", bits, description))
+        )?;
+        writeln!(methods, "    #[inline(always)]")?;
+        writeln!(methods, "    pub fn {}(&self) -> {} {{", snake, reader)?;
+        writeln!(methods, "        {}::new({})", reader, value)?;
+        writeln!(methods, "    }}")?;
+    }
+
+    if writable {
+        let complete = complete(&write_variants);
+        let writer = format!("{}_W", upper);
+        writeln!(
+            defs,
+            "{}",
+            doc(&format!("Field `{}` writer - {}", field.name, description))
+        )?;
+        let safety = if complete { ", crate::Safe" } else { "" };
+        let writer_type = match (bit, write_variants.is_empty(), ux) {
+            (true, true, _) => "crate::BitWriter<'a, REG>".to_string(),
+            (true, false, _) => format!("crate::BitWriter<'a, REG, {}>", write_enum_name),
+            (false, true, "u8") => format!("crate::FieldWriter<'a, REG, {}>", width),
+            (false, true, ux) => format!("crate::FieldWriter<'a, REG, {}, {}>", width, ux),
+            (false, false, _) => format!(
+                "crate::FieldWriter<'a, REG, {}, {}{}>",
+                width, write_enum_name, safety
+            ),
+        };
+        writeln!(defs, "pub type {}<'a, REG> = {};", writer, writer_type)?;
+
+        if !write_variants.is_empty() {
+            writeln!(defs, "impl<'a, REG> {}<'a, REG>", writer)?;
+            writeln!(defs, "where")?;
+            writeln!(defs, "    REG: crate::Writable + crate::RegisterSpec,")?;
+            if !bit {
+                writeln!(defs, "    REG::Ux: From<{}>,", ux)?;
+            }
+            writeln!(defs, "{{")?;
+            for (name, _, value) in &write_variants {
+                let description = value.description.as_deref().unwrap_or(&value.name);
+                writeln!(defs, "    {}", doc(description))?;
+                writeln!(defs, "    #[inline(always)]")?;
+                writeln!(
+                    defs,
+                    "    pub fn {}(self) -> &'a mut crate::W<REG> {{",
+                    snake_name(name)
+                )?;
+                writeln!(defs, "        self.variant({}::{})", write_enum_name, name)?;
+                writeln!(defs, "    }}")?;
+            }
+            writeln!(defs, "}}")?;
+        }
+
+        let methods = &mut code.write_methods;
+        writeln!(
+            methods,
+            "    {}",
+            doc(&format!("{} - {}", bits, description))
+        )?;
+        writeln!(methods, "    #[inline(always)]")?;
+        writeln!(methods, "    #[must_use]")?;
+        writeln!(
+            methods,
+            "    pub fn {}(&mut self) -> {}<'_, {}> {{",
+            snake, writer, code.spec
+        )?;
+        writeln!(methods, "        {}::new(self, {})", writer, offset)?;
+        writeln!(methods, "    }}")?;
+    }
+    Ok(())
+}
+
+/// Write the register type and module of a register.
+fn write_register(
+    args: &Args,
+    device: &Device,
+    register: &Register,
+    out: &mut String,
+) -> std::fmt::Result {
+    let upper = upper_name(&register.name);
+    let snake = snake_name(&register.name);
+    let spec = format!("{}_SPEC", upper);
+    let register_width = register.size.unwrap_or(device.size);
+    let (ux, _) = rust_type(register_width);
+    let access = register.access.unwrap_or(device.access);
+    let description = register.description.as_deref().unwrap_or(&register.name);
+
+    let mut code = FieldCode {
+        spec: spec.clone(),
+        register_width,
+        ..FieldCode::default()
+    };
+    let mut names = HashSet::new();
+    // Bits that `modify` must write as 1 or 0 to leave them unchanged
+    let (mut one_to_modify, mut zero_to_modify) = (0, 0);
+    for field in &register.fields {
+        let warnings = args.warning_count();
+        match (field.bit_offset, field.bit_width) {
+            (Some(offset), Some(width))
+                if width > 0
+                    && u64::from(offset) + u64::from(width) <= u64::from(register_width) =>
+            {
+                if !names.insert(snake_name(&field.name)) {
+                    args.warn(duplicate_name(&field.name));
+                } else {
+                    let access = field.access.unwrap_or(access);
+                    write_field(args, field, offset, width, access, &mut code)?;
+                }
+                let bits = mask(width) << offset;
+                let modified_write_values = match field.access {
+                    Some(_) => field.modified_write_values,
+                    None => register.modified_write_values,
+                };
+                match modified_write_values {
+                    Some(ModifiedWriteValues::OneToClear)
+                    | Some(ModifiedWriteValues::OneToSet)
+                    | Some(ModifiedWriteValues::OneToToggle) => one_to_modify |= bits,
+                    Some(ModifiedWriteValues::ZeroToClear)
+                    | Some(ModifiedWriteValues::ZeroToSet)
+                    | Some(ModifiedWriteValues::ZeroToToggle) => zero_to_modify |= bits,
+                    _ => {}
+                }
+            }
+            (Some(offset), Some(width)) if width > 0 => args.warn(Error::FieldTooBig {
+                context: Box::default(),
+                bit_offset: offset,
+                bit_width: width,
+                register_width,
+            }),
+            _ => {}
+        }
+        args.locate_warnings(warnings, |w| w.in_field(&field.name));
+    }
+
+    writeln!(out, "{}", doc(description))?;
+    writeln!(out, "pub type {} = crate::Reg<{}::{}>;", upper, snake, spec)?;
+    writeln!(out, "{}", doc(description))?;
+    writeln!(out, "pub mod {} {{", snake)?;
+    if access != Access::WriteOnly {
+        writeln!(out, "#[doc = \"Register `{}` reader\"]", register.name)?;
+        writeln!(out, "pub type R = crate::R<{}>;", spec)?;
+    }
+    if access != Access::ReadOnly {
+        writeln!(out, "#[doc = \"Register `{}` writer\"]", register.name)?;
+        writeln!(out, "pub type W = crate::W<{}>;", spec)?;
+    }
+    out.push_str(&code.definitions);
+    if access != Access::WriteOnly && !code.read_methods.is_empty() {
+        writeln!(out, "impl R {{")?;
+        out.push_str(&code.read_methods);
+        writeln!(out, "}}")?;
+    }
+    if access != Access::ReadOnly && !code.write_methods.is_empty() {
+        writeln!(out, "impl W {{")?;
+        out.push_str(&code.write_methods);
+        writeln!(out, "}}")?;
+    }
+    writeln!(out, "{}", doc(description))?;
+    writeln!(out, "pub struct {};", spec)?;
+    writeln!(out, "impl crate::RegisterSpec for {} {{", spec)?;
+    writeln!(out, "    type Ux = {};", ux)?;
+    writeln!(out, "}}")?;
+    if access != Access::WriteOnly {
+        writeln!(out, "impl crate::Readable for {} {{}}", spec)?;
+    }
+    if access != Access::ReadOnly {
+        writeln!(out, "impl crate::Writable for {} {{", spec)?;
+        writeln!(
+            out,
+            "    const ZERO_TO_MODIFY_FIELDS_BITMAP: {} = 0x{:x};",
+            ux, zero_to_modify
+        )?;
+        writeln!(
+            out,
+            "    const ONE_TO_MODIFY_FIELDS_BITMAP: {} = 0x{:x};",
+            ux, one_to_modify
+        )?;
+        writeln!(out, "}}")?;
+    }
+    let reset_value = register.reset_value.unwrap_or(device.reset_value) & mask(register_width);
+    writeln!(out, "impl crate::Resettable for {} {{", spec)?;
+    writeln!(out, "    const RESET_VALUE: {} = 0x{:x};", ux, reset_value)?;
+    writeln!(out, "}}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// A register, or an array of registers, in a register block.
+struct Member<'a> {
+    name: String,
+    type_name: String,
+    offset: u64,
+    count: u64,
+    bytes: u64,
+    register: &'a Register,
+}
+
+/// Lay out the registers of a peripheral, sorted by offset. Register
+/// arrays become Rust arrays if their elements are contiguous, and are
+/// expanded into single registers otherwise.
+fn members<'a>(args: &Args, device: &Device, peripheral: &'a Peripheral) -> Vec<Member<'a>> {
+    let mut members = vec![];
+    for register in &peripheral.registers {
+        let (_, bytes) = rust_type(register.size.unwrap_or(device.size));
+        if register.address_offset % bytes != 0 {
+            args.warn(
+                Error::invalid_value("offset", &format!("0x{:x}", register.address_offset))
+                    .in_register(&register.name),
+            );
+            continue;
+        }
+        let type_name = upper_name(&register.name);
+        match register.dim {
+            Some(ref dim) if dim.dim_increment == bytes => members.push(Member {
+                name: snake_name(&register.name),
+                type_name,
+                offset: register.address_offset,
+                count: u64::from(dim.dim),
+                bytes,
+                register,
+            }),
+            Some(ref dim) => {
+                for (i, index) in dim.indices().iter().enumerate() {
+                    members.push(Member {
+                        name: snake_name(&register.name.replace("%s", index)),
+                        type_name: type_name.clone(),
+                        offset: register.address_offset + i as u64 * dim.dim_increment,
+                        count: 1,
+                        bytes,
+                        register,
+                    });
+                }
+            }
+            None => members.push(Member {
+                name: snake_name(&register.name),
+                type_name,
+                offset: register.address_offset,
+                count: 1,
+                bytes,
+                register,
+            }),
+        }
+    }
+    members.sort_by_key(|m| m.offset);
+    members
+}
+
+/// Write the module of a peripheral: its register block and registers.
+/// Registers that overlap an earlier one are reached through a pointer
+/// instead of a struct member.
+fn write_peripheral_module(
+    args: &Args,
+    device: &Device,
+    peripheral: &Peripheral,
+) -> Result<String> {
+    let mut out = String::new();
+    let mut fields = String::new();
+    let mut methods = String::new();
+    let mut registers = String::new();
+    let mut names = HashSet::new();
+    let mut type_names = HashSet::new();
+    let mut position = 0;
+    let mut reserved = 0;
+
+    for member in members(args, device, peripheral) {
+        if !names.insert(member.name.clone()) {
+            args.warn(duplicate_name(&member.name).in_register(&member.register.name));
+            continue;
+        }
+        if type_names.insert(member.type_name.clone()) {
+            let warnings = args.warning_count();
+            write_register(args, device, member.register, &mut registers)?;
+            args.locate_warnings(warnings, |w| w.in_register(&member.register.name));
+        }
+
+        let description = member
+            .register
+            .description
+            .as_deref()
+            .unwrap_or(&member.register.name);
+        writeln!(
+            methods,
+            "    {}",
+            doc(&format!("0x{:02x} - {}", member.offset, description))
+        )?;
+        writeln!(methods, "    #[inline(always)]")?;
+        let by_pointer = member.offset < position;
+        match (member.count > 1, by_pointer) {
+            (false, false) => {
+                writeln!(
+                    methods,
+                    "    pub const fn {}(&self) -> &{} {{",
+                    member.name, member.type_name
+                )?;
+                writeln!(methods, "        &self.{}", member.name)?;
+            }
+            (true, false) => {
+                writeln!(
+                    methods,
+                    "    pub const fn {}(&self, n: usize) -> &{} {{",
+                    member.name, member.type_name
+                )?;
+                writeln!(methods, "        &self.{}[n]", member.name)?;
+            }
+            (false, true) => {
+                writeln!(
+                    methods,
+                    "    pub const fn {}(&self) -> &{} {{",
+                    member.name, member.type_name
+                )?;
+                writeln!(
+                    methods,
+                    "        unsafe {{ &*(self as *const Self).cast::<u8>().add({}).cast() }}",
+                    member.offset
+                )?;
+            }
+            (true, true) => {
+                writeln!(
+                    methods,
+                    "    pub const fn {}(&self, n: usize) -> &{} {{",
+                    member.name, member.type_name
+                )?;
+                writeln!(methods, "        assert!(n < {});", member.count)?;
+                writeln!(
+                    methods,
+                    "        unsafe {{ &*(self as *const Self).cast::<u8>().add({}).add({} * n).cast() }}",
+                    member.offset, member.bytes
+                )?;
+            }
+        }
+        writeln!(methods, "    }}")?;
+        if member.count > 1 {
+            writeln!(methods, "    #[doc = \"Iterator for the array\"]")?;
+            writeln!(methods, "    #[inline(always)]")?;
+            writeln!(
+                methods,
+                "    pub fn {}_iter(&self) -> impl Iterator<Item = &{}> {{",
+                member.name, member.type_name
+            )?;
+            writeln!(
+                methods,
+                "        (0..{}).map(move |n| self.{}(n))",
+                member.count, member.name
+            )?;
+            writeln!(methods, "    }}")?;
+        }
+        if by_pointer {
+            continue;
+        }
+
+        if member.offset > position {
+            writeln!(
+                fields,
+                "    _reserved{}: [u8; 0x{:x}],",
+                reserved,
+                member.offset - position
+            )?;
+            reserved += 1;
+        }
+        if member.count > 1 {
+            writeln!(
+                fields,
+                "    {}: [{}; {}],",
+                member.name, member.type_name, member.count
+            )?;
+        } else {
+            writeln!(fields, "    {}: {},", member.name, member.type_name)?;
+        }
+        position = member.offset + member.bytes * member.count;
+    }
+
+    writeln!(out, "#[doc = \"Register block\"]")?;
+    writeln!(out, "#[repr(C)]")?;
+    writeln!(out, "pub struct RegisterBlock {{")?;
+    out.push_str(&fields);
+    writeln!(out, "}}")?;
+    writeln!(out, "impl RegisterBlock {{")?;
+    out.push_str(&methods);
+    writeln!(out, "}}")?;
+    out.push_str(&registers);
+    Ok(out)
+}
+
+fn is_cortex_m(device: &Device) -> bool {
+    device.cpu.name.starts_with("CM")
+}
+
+/// The package name of the crate.
+fn crate_name(device: &Device) -> String {
+    device
+        .name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn write_cargo_toml(device: &Device) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "[package]")?;
+    writeln!(out, "name = {:?}", crate_name(device))?;
+    writeln!(out, "version = \"0.1.0\"")?;
+    writeln!(
+        out,
+        "description = {:?}",
+        format!("Peripheral access crate for the {}", device.name)
+    )?;
+    writeln!(out, "edition = \"2021\"")?;
+    writeln!(out)?;
+    writeln!(out, "[dependencies]")?;
+    writeln!(
+        out,
+        "critical-section = {{ version = \"1.1\", optional = true }}"
+    )?;
+    if is_cortex_m(device) {
+        writeln!(out, "cortex-m = \"0.7.6\"")?;
+        writeln!(
+            out,
+            "cortex-m-rt = {{ version = \"0.7.3\", optional = true }}"
+        )?;
+    }
+    writeln!(out, "vcell = \"0.1.3\"")?;
+    writeln!(out)?;
+    writeln!(out, "[features]")?;
+    if is_cortex_m(device) {
+        writeln!(out, "rt = [\"cortex-m-rt/device\"]")?;
+    }
+    Ok(out)
+}
+
+/// A linker script stub; TIXML does not describe the memories.
+fn write_memory_x(device: &Device) -> String {
+    let (flash, ram) = if is_cortex_m(device) {
+        ("FLASH : ORIGIN = 0x00000000", "RAM : ORIGIN = 0x20000000")
+    } else {
+        ("ROM : ORIGIN = 0x00004400", "RAM : ORIGIN = 0x00002000")
+    };
+    format!(
+        "/* Memory layout of the {}: check the origins and fill in the lengths\n   \
+         from the data sheet. */\nMEMORY\n{{\n  {}, LENGTH = 0K\n  {}, LENGTH = 0K\n}}\n",
+        device.name, flash, ram
+    )
+}
+
+const BUILD_RS: &str = r#"use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    if env::var_os("CARGO_FEATURE_RT").is_some() {
+        let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        File::create(out.join("device.x"))
+            .unwrap()
+            .write_all(include_bytes!("device.x"))
+            .unwrap();
+        println!("cargo:rustc-link-search={}", out.display());
+        println!("cargo:rerun-if-changed=device.x");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
+"#;
+
+/// The interrupts of all peripherals, by number, without duplicates.
+fn interrupts(args: &Args, device: &Device) -> Vec<(String, u32, String)> {
+    let mut interrupts = vec![];
+    let mut names = HashSet::new();
+    let mut values = HashSet::new();
+    for peripheral in &device.peripherals {
+        for interrupt in &peripheral.interrupts {
+            let name = upper_name(&interrupt.name);
+            if !names.insert(name.clone()) || !values.insert(interrupt.value) {
+                args.warn(duplicate_name(&interrupt.name).in_peripheral(&peripheral.name));
+                continue;
+            }
+            let description = interrupt.description.clone().unwrap_or_default();
+            interrupts.push((name, interrupt.value, description));
+        }
+    }
+    interrupts.sort_by_key(|i| i.1);
+    interrupts
+}
+
+fn write_interrupts(
+    device: &Device,
+    interrupts: &[(String, u32, String)],
+    out: &mut String,
+) -> std::fmt::Result {
+    writeln!(
+        out,
+        "#[doc = \"Number available in the NVIC for configuring priority\"]"
+    )?;
+    writeln!(
+        out,
+        "pub const NVIC_PRIO_BITS: u8 = {};",
+        device.cpu.nvic_prio_bits
+    )?;
+    writeln!(
+        out,
+        "pub use cortex_m::peripheral::Peripherals as CorePeripherals;"
+    )?;
+    writeln!(out, "#[cfg(feature = \"rt\")]")?;
+    writeln!(out, "pub use cortex_m_rt::interrupt;")?;
+    writeln!(out, "#[cfg(feature = \"rt\")]")?;
+    writeln!(out, "extern \"C\" {{")?;
+    for (name, _, _) in interrupts {
+        writeln!(out, "    fn {}();", name)?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out, "#[doc(hidden)]")?;
+    writeln!(out, "#[repr(C)]")?;
+    writeln!(out, "pub union Vector {{")?;
+    writeln!(out, "    _handler: unsafe extern \"C\" fn(),")?;
+    writeln!(out, "    _reserved: u32,")?;
+    writeln!(out, "}}")?;
+    let count = interrupts.last().map_or(0, |i| i.1 + 1);
+    writeln!(out, "#[cfg(feature = \"rt\")]")?;
+    writeln!(out, "#[doc(hidden)]")?;
+    writeln!(out, "#[link_section = \".vector_table.interrupts\"]")?;
+    writeln!(out, "#[no_mangle]")?;
+    writeln!(out, "pub static __INTERRUPTS: [Vector; {}] = [", count)?;
+    let mut next = 0;
+    for (name, value, _) in interrupts {
+        for _ in next..*value {
+            writeln!(out, "    Vector {{ _reserved: 0 }},")?;
+        }
+        writeln!(out, "    Vector {{ _handler: {} }},", name)?;
+        next = value + 1;
+    }
+    writeln!(out, "];")?;
+    // An enum without variants cannot have a representation
+    if interrupts.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "#[cfg(feature = \"rt\")]")?;
+    writeln!(out, "pub use self::Interrupt as interrupt;")?;
+    writeln!(out, "#[doc = \"Enumeration of all the interrupts.\"]")?;
+    writeln!(out, "#[derive(Copy, Clone, Debug, PartialEq, Eq)]")?;
+    writeln!(out, "#[repr(u16)]")?;
+    writeln!(out, "pub enum Interrupt {{")?;
+    for (name, value, description) in interrupts {
+        writeln!(out, "    {}", doc(&format!("{} - {}", value, description)))?;
+        writeln!(out, "    {} = {},", name, value)?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(
+        out,
+        "unsafe impl cortex_m::interrupt::InterruptNumber for Interrupt {{"
+    )?;
+    writeln!(out, "    #[inline(always)]")?;
+    writeln!(out, "    fn number(self) -> u16 {{")?;
+    writeln!(out, "        self as u16")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_peripheral_struct(
+    peripheral: &Peripheral,
+    module: &str,
+    base_address: u64,
+    out: &mut String,
+) -> std::fmt::Result {
+    let name = upper_name(&peripheral.name);
+    let description = peripheral
+        .description
+        .as_deref()
+        .unwrap_or(&peripheral.name);
+    writeln!(out, "{}", doc(description))?;
+    writeln!(out, "pub struct {} {{", name)?;
+    writeln!(out, "    _marker: PhantomData<*const ()>,")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "unsafe impl Send for {} {{}}", name)?;
+    writeln!(out, "impl {} {{", name)?;
+    writeln!(out, "    #[doc = \"Pointer to the register block\"]")?;
+    writeln!(
+        out,
+        "    pub const PTR: *const {}::RegisterBlock = 0x{:08x} as *const _;",
+        module, base_address
+    )?;
+    writeln!(
+        out,
+        "    #[doc = \"Return the pointer to the register block\"]"
+    )?;
+    writeln!(out, "    #[inline(always)]")?;
+    writeln!(
+        out,
+        "    pub const fn ptr() -> *const {}::RegisterBlock {{",
+        module
+    )?;
+    writeln!(out, "        Self::PTR")?;
+    writeln!(out, "    }}")?;
+    writeln!(
+        out,
+        "    #[doc = \"Steal an instance of this peripheral\\n\\n# Safety\\n\\n\
+         Ensure that the new instance of the peripheral cannot be used in a way\\n\
+         that may race with any existing instances.\"]"
+    )?;
+    writeln!(out, "    #[inline(always)]")?;
+    writeln!(out, "    pub unsafe fn steal() -> Self {{")?;
+    writeln!(out, "        Self {{ _marker: PhantomData }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "impl Deref for {} {{", name)?;
+    writeln!(out, "    type Target = {}::RegisterBlock;", module)?;
+    writeln!(out, "    #[inline(always)]")?;
+    writeln!(out, "    fn deref(&self) -> &Self::Target {{")?;
+    writeln!(out, "        unsafe {{ &*Self::PTR }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "impl core::fmt::Debug for {} {{", name)?;
+    writeln!(
+        out,
+        "    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {{"
+    )?;
+    writeln!(out, "        f.debug_struct({:?}).finish()", name)?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents).map_err(|e| Error::from(e).in_file(path))
+}
+
+/// Write a device as a Rust peripheral access crate into `dir`, which is
+/// created if needed.
+pub fn write_pac(args: &Args, device: &Device, dir: &Path) -> Result<()> {
+    let src = dir.join("src");
+    fs::create_dir_all(&src).map_err(|e| Error::from(e).in_file(&src))?;
+
+    let mut lib = String::new();
+    writeln!(
+        lib,
+        "#![doc = {:?}]",
+        format!(
+            "Peripheral access API for {} microcontrollers (generated by tixml2svd)",
+            device.name
+        )
+    )?;
+    writeln!(lib, "#![allow(non_camel_case_types)]")?;
+    writeln!(lib, "#![allow(non_snake_case)]")?;
+    writeln!(lib, "#![no_std]")?;
+    writeln!(lib, "use core::marker::PhantomData;")?;
+    writeln!(lib, "use core::ops::Deref;")?;
+    writeln!(lib, "#[allow(unused_imports)]")?;
+    writeln!(lib, "use generic::*;")?;
+    writeln!(
+        lib,
+        "#[doc = \"Common register and bit access and modify traits\"]"
+    )?;
+    writeln!(lib, "pub mod generic;")?;
+
+    if is_cortex_m(device) {
+        let interrupts = interrupts(args, device);
+        write_interrupts(device, &interrupts, &mut lib)?;
+        let mut device_x = String::new();
+        for (name, _, _) in &interrupts {
+            writeln!(device_x, "PROVIDE({} = DefaultHandler);", name)?;
+        }
+        write_file(&dir.join("device.x"), &device_x)?;
+        write_file(&dir.join("build.rs"), BUILD_RS)?;
+    }
+
+    let mut names = HashSet::new();
+    let mut modules = vec![];
+    let mut singletons = vec![];
+    for peripheral in &device.peripherals {
+        let warnings = args.warning_count();
+        let name = upper_name(&peripheral.name);
+        let module = snake_name(&peripheral.name);
+        let base_address = match peripheral.base_address {
+            Some(base_address) => base_address,
+            None => {
+                args.warn(Error::MissingValue {
+                    context: Box::default(),
+                    key: "baseAddress".to_string(),
+                });
+                args.locate_warnings(warnings, |w| w.in_peripheral(&peripheral.name));
+                continue;
+            }
+        };
+        if !names.insert(name.clone()) {
+            args.warn(duplicate_name(&peripheral.name));
+            args.locate_warnings(warnings, |w| w.in_peripheral(&peripheral.name));
+            continue;
+        }
+        if args.verbose > 0 {
+            eprintln!("Writing Rust code for peripheral {}", peripheral.name);
+        }
+
+        match peripheral.derived_from {
+            Some(ref original) if modules.contains(&snake_name(original)) => {
+                let original = snake_name(original);
+                write_peripheral_struct(peripheral, &original, base_address, &mut lib)?;
+                writeln!(lib, "pub use self::{} as {};", original, module)?;
+            }
+            _ => {
+                let code = write_peripheral_module(args, device, peripheral)?;
+                write_file(&src.join(format!("{}.rs", module)), &code)?;
+                write_peripheral_struct(peripheral, &module, base_address, &mut lib)?;
+                let description = peripheral
+                    .description
+                    .as_deref()
+                    .unwrap_or(&peripheral.name);
+                writeln!(lib, "{}", doc(description))?;
+                writeln!(lib, "pub mod {};", module)?;
+                modules.push(module);
+            }
+        }
+        singletons.push(name);
+        args.locate_warnings(warnings, |w| w.in_peripheral(&peripheral.name));
+    }
+
+    writeln!(lib, "#[no_mangle]")?;
+    writeln!(lib, "static mut DEVICE_PERIPHERALS: bool = false;")?;
+    writeln!(lib, "#[doc = \"All the peripherals.\"]")?;
+    writeln!(lib, "#[allow(non_snake_case)]")?;
+    writeln!(lib, "pub struct Peripherals {{")?;
+    for name in &singletons {
+        writeln!(lib, "    #[doc = {:?}]", name)?;
+        writeln!(lib, "    pub {}: {},", name, name)?;
+    }
+    writeln!(lib, "}}")?;
+    writeln!(lib, "impl Peripherals {{")?;
+    writeln!(lib, "    #[doc = \"Returns all the peripherals *once*.\"]")?;
+    writeln!(lib, "    #[cfg(feature = \"critical-section\")]")?;
+    writeln!(lib, "    #[inline]")?;
+    writeln!(lib, "    pub fn take() -> Option<Self> {{")?;
+    writeln!(lib, "        critical_section::with(|_| {{")?;
+    writeln!(lib, "            if unsafe {{ DEVICE_PERIPHERALS }} {{")?;
+    writeln!(lib, "                return None;")?;
+    writeln!(lib, "            }}")?;
+    writeln!(lib, "            Some(unsafe {{ Peripherals::steal() }})")?;
+    writeln!(lib, "        }})")?;
+    writeln!(lib, "    }}")?;
+    writeln!(
+        lib,
+        "    #[doc = \"Unchecked version of `Peripherals::take`.\\n\\n# Safety\\n\\n\
+         Each of the returned peripherals must be used at most once.\"]"
+    )?;
+    writeln!(lib, "    #[inline]")?;
+    writeln!(lib, "    pub unsafe fn steal() -> Self {{")?;
+    writeln!(lib, "        DEVICE_PERIPHERALS = true;")?;
+    writeln!(lib, "        Peripherals {{")?;
+    for name in &singletons {
+        writeln!(lib, "            {}: {}::steal(),", name, name)?;
+    }
+    writeln!(lib, "        }}")?;
+    writeln!(lib, "    }}")?;
+    writeln!(lib, "}}")?;
+
+    write_file(&src.join("lib.rs"), &lib)?;
+    write_file(&src.join("generic.rs"), GENERIC)?;
+    write_file(&dir.join("Cargo.toml"), &write_cargo_toml(device)?)?;
+    write_file(&dir.join("memory.x"), &write_memory_x(device))?;
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Fixing known mistakes in TIXML files with declarative patches.
+//!
+//! A patch file is YAML (`.yaml` or `.yml`) or TOML (any other
+//! extension). It lists peripherals, each selected by `name` and/or
+//! `module` (the module file, such as `msp430/RTC_C_208.0`), and
+//! optionally restricted to some `devices`. Registers and fields are
+//! selected by `name`, enumerated values by `name` and/or `value`. Names
+//! and device names may end in `*` to match any name starting with the
+//! rest, and `duplicate = true` selects only peripherals whose name is
+//! already used by an earlier one.
+//!
+//! ```toml
+//! [[peripheral]]
+//! module = "msp430/RTC_C_208.0"
+//!
+//! [[peripheral.register]]
+//! name = "RTCCTL13"
+//!
+//! [[peripheral.register.field]]
+//! name = "RTCSSEL"
+//!
+//! [[peripheral.register.field.enum]]
+//! name = "LFXT"
+//! value = "0x1"
+//! rename = "LFXT_"
+//! ```
+//!
+//! The fixes needed for the Code Composer Studio v8 device files are
+//! built in, see `patches/ccsv8.toml`.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::model::{Device, EnumeratedValue, Field, Peripheral, Register};
+use crate::reader::same_value;
+use crate::Args;
+
+/// A set of changes to devices.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    #[serde(default)]
+    pub peripheral: Vec<PeripheralPatch>,
+}
+
+/// Changes to the peripherals selected by `name` and `module`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeripheralPatch {
+    /// Devices to patch; all if empty
+    #[serde(default)]
+    pub devices: Vec<String>,
+    pub name: Option<String>,
+    pub module: Option<String>,
+    /// Only select peripherals whose name is used by an earlier one
+    #[serde(default)]
+    pub duplicate: bool,
+    #[serde(default)]
+    pub delete: bool,
+    pub rename: Option<String>,
+    pub base_address: Option<u64>,
+    #[serde(default)]
+    pub register: Vec<RegisterPatch>,
+}
+
+/// Changes to the registers selected by `name`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterPatch {
+    pub name: String,
+    #[serde(default)]
+    pub delete: bool,
+    pub rename: Option<String>,
+    pub reset_value: Option<u64>,
+    #[serde(default)]
+    pub field: Vec<FieldPatch>,
+}
+
+/// Changes to the fields selected by `name`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldPatch {
+    pub name: String,
+    #[serde(default)]
+    pub delete: bool,
+    pub rename: Option<String>,
+    pub bit_offset: Option<u32>,
+    pub bit_width: Option<u32>,
+    #[serde(default, rename = "enum")]
+    pub enumerated_values: Vec<EnumPatch>,
+}
+
+/// Changes to the enumerated values selected by `name` and `value`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumPatch {
+    pub name: Option<String>,
+    pub value: Option<String>,
+    #[serde(default)]
+    pub delete: bool,
+    pub rename: Option<String>,
+    pub new_value: Option<String>,
+}
+
+fn syntax_error(message: String) -> Error {
+    Error::Syntax {
+        context: Box::default(),
+        message,
+    }
+}
+
+/// The line of a byte offset into a text.
+fn line_of(text: &str, offset: usize) -> u64 {
+    text[..offset.min(text.len())].matches('\n').count() as u64 + 1
+}
+
+fn parse_toml(text: &str) -> Result<Patch> {
+    toml::from_str(text).map_err(|e| {
+        let error = syntax_error(e.message().to_string());
+        match e.span() {
+            Some(span) => error.at_line(line_of(text, span.start)),
+            None => error,
+        }
+    })
+}
+
+fn parse_yaml(text: &str) -> Result<Patch> {
+    // The message already gives the line and column
+    serde_yaml::from_str(text).map_err(|e| syntax_error(e.to_string()))
+}
+
+/// Read a patch file, in YAML or TOML as chosen by extension.
+pub fn read_patch_file<P: AsRef<Path>>(path: P) -> Result<Patch> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "yaml" | "yml" => parse_yaml(&text),
+        _ => parse_toml(&text),
+    }
+    .map_err(|e| e.in_file(path))
+}
+
+/// The fixes for the Code Composer Studio v8 device files.
+pub fn builtin_patch() -> Patch {
+    parse_toml(include_str!("../patches/ccsv8.toml")).expect("invalid built-in patch")
+}
+
+/// Whether a name matches a pattern, which may end in `*`.
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+fn patch_enumerated_values(patch: &EnumPatch, values: &mut Vec<EnumeratedValue>) {
+    let selected = |value: &EnumeratedValue| {
+        patch.name.as_ref().is_none_or(|n| matches(n, &value.name))
+            && patch
+                .value
+                .as_ref()
+                .is_none_or(|v| same_value(v, &value.value))
+    };
+    if patch.delete {
+        values.retain(|v| !selected(v));
+        return;
+    }
+    for value in values.iter_mut().filter(|v| selected(v)) {
+        if let Some(ref name) = patch.rename {
+            value.name = name.clone();
+        }
+        if let Some(ref new_value) = patch.new_value {
+            value.value = new_value.clone();
+        }
+    }
+}
+
+fn patch_fields(patch: &FieldPatch, fields: &mut Vec<Field>) {
+    if patch.delete {
+        fields.retain(|f| !matches(&patch.name, &f.name));
+        return;
+    }
+    for field in fields.iter_mut().filter(|f| matches(&patch.name, &f.name)) {
+        if let Some(ref name) = patch.rename {
+            field.name = name.clone();
+        }
+        if let Some(bit_offset) = patch.bit_offset {
+            field.bit_offset = Some(bit_offset);
+        }
+        if let Some(bit_width) = patch.bit_width {
+            field.bit_width = Some(bit_width);
+        }
+        for enum_patch in &patch.enumerated_values {
+            patch_enumerated_values(enum_patch, &mut field.enumerated_values);
+        }
+    }
+}
+
+fn patch_registers(patch: &RegisterPatch, registers: &mut Vec<Register>) {
+    if patch.delete {
+        registers.retain(|r| !matches(&patch.name, &r.name));
+        return;
+    }
+    for register in registers
+        .iter_mut()
+        .filter(|r| matches(&patch.name, &r.name))
+    {
+        if let Some(ref name) = patch.rename {
+            register.name = name.clone();
+        }
+        if let Some(reset_value) = patch.reset_value {
+            register.reset_value = Some(reset_value);
+        }
+        for field_patch in &patch.field {
+            patch_fields(field_patch, &mut register.fields);
+        }
+    }
+}
+
+impl PeripheralPatch {
+    fn selects(&self, device_name: &str, peripheral: &Peripheral) -> bool {
+        if self.name.is_none() && self.module.is_none() {
+            return false;
+        }
+        (self.devices.is_empty() || self.devices.iter().any(|d| matches(d, device_name)))
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|n| matches(n, &peripheral.name))
+            && self.module.as_ref().is_none_or(|m| {
+                peripheral
+                    .module
+                    .as_ref()
+                    .is_some_and(|module| matches(m, module))
+            })
+    }
+}
+
+/// Apply a patch to the peripherals of a device.
+pub fn apply_patch(args: &Args, patch: &Patch, device: &mut Device) {
+    let device_name = &device.name;
+    for peripheral_patch in &patch.peripheral {
+        let mut names = HashSet::new();
+        device.peripherals.retain_mut(|peripheral| {
+            let duplicate = !names.insert(peripheral.name.clone());
+            if !peripheral_patch.selects(device_name, peripheral)
+                || (peripheral_patch.duplicate && !duplicate)
+            {
+                return true;
+            }
+            if args.verbose > 0 {
+                eprintln!("Patching peripheral {}", peripheral.name);
+            }
+            if peripheral_patch.delete {
+                return false;
+            }
+            if let Some(ref name) = peripheral_patch.rename {
+                peripheral.name = name.clone();
+            }
+            if let Some(base_address) = peripheral_patch.base_address {
+                peripheral.base_address = Some(base_address);
+            }
+            for register_patch in &peripheral_patch.register {
+                patch_registers(register_patch, &mut peripheral.registers);
+            }
+            true
+        });
+    }
+}
@@ -0,0 +1,1101 @@
+//! Reading TIXML device and peripheral files into the device model.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Serialize;
+use unicode_bom::Bom;
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::name::OwnedName;
+use xml::reader::EventReader;
+use xml::reader::XmlEvent::{EndDocument, EndElement, StartElement};
+
+use crate::cpus::apply_known_cpu;
+use crate::error::{Error, Result, Warning};
+use crate::group::group_name;
+use crate::model::{
+    Access, Device, EnumeratedValue, Field, ModifiedWriteValues, Peripheral, ReadAction, Register,
+};
+use crate::Args;
+
+fn get_name_from_description(description: &str) -> String {
+    let replaces = [
+        (',', '_'),
+        ('.', '_'),
+        (':', '\0'),
+        ('/', '_'),
+        ('#', '\0'),
+        ('-', '_'),
+        (' ', '_'),
+    ];
+    let mut name = description
+        .replace('-', "")
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<&str>>()
+        .join("_");
+    name = name.split("#br#").next().unwrap_or("").to_string();
+    name = name.split(':').next().unwrap_or("").to_string();
+    for (from, to) in &replaces {
+        name = name.replace(&from.to_string(), &to.to_string());
+    }
+    name.to_uppercase()
+}
+
+/// The access type, write side effect and read side effect of a TIXML
+/// `rwaccess` value.
+type AccessInfo = (
+    Option<Access>,
+    Option<ModifiedWriteValues>,
+    Option<ReadAction>,
+);
+
+fn read_access(args: &Args, ti_access: &str) -> AccessInfo {
+    match ti_access {
+        "RO" | "R=1/W=0" | "R" => (Some(Access::ReadOnly), None, None),
+        "WO" | "R=0/W=1" | "W" => (Some(Access::WriteOnly), None, None),
+        "RW" | "R=1/W=1" | "R/W" => (Some(Access::ReadWrite), None, None),
+        "R/W1TC" | "R/W1C" | "RW1C" | "W1C" => (
+            Some(Access::ReadWrite),
+            Some(ModifiedWriteValues::OneToClear),
+            None,
+        ),
+        "R/W1TS" | "R/W1S" | "RW1S" | "W1S" => (
+            Some(Access::ReadWrite),
+            Some(ModifiedWriteValues::OneToSet),
+            None,
+        ),
+        "R/W1T" | "W1T" => (
+            Some(Access::ReadWrite),
+            Some(ModifiedWriteValues::OneToToggle),
+            None,
+        ),
+        "R/W0C" | "RW0C" | "W0C" => (
+            Some(Access::ReadWrite),
+            Some(ModifiedWriteValues::ZeroToClear),
+            None,
+        ),
+        "R/W0S" | "RW0S" | "W0S" => (
+            Some(Access::ReadWrite),
+            Some(ModifiedWriteValues::ZeroToSet),
+            None,
+        ),
+        "W1TC" => (
+            Some(Access::WriteOnly),
+            Some(ModifiedWriteValues::OneToClear),
+            None,
+        ),
+        "W1TS" => (
+            Some(Access::WriteOnly),
+            Some(ModifiedWriteValues::OneToSet),
+            None,
+        ),
+        "RC" | "R/C" | "ROC" => (Some(Access::ReadOnly), None, Some(ReadAction::Clear)),
+        unknown => {
+            args.warn(Error::invalid_value("rwaccess", unknown));
+            (None, None, None)
+        }
+    }
+}
+
+/// Parse a number given in decimal, `0x` hex, or `0b` or `#b` binary.
+pub(crate) fn parse_number(key: &str, value: &str) -> Result<u64> {
+    let lower = value.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower
+        .strip_prefix("0b")
+        .or_else(|| lower.strip_prefix("#b"))
+    {
+        u64::from_str_radix(binary, 2)
+    } else {
+        u64::from_str(value)
+    };
+    parsed.map_err(|_| Error::invalid_value(key, value))
+}
+
+/// Whether two enumerated values are the same, as numbers if possible.
+pub(crate) fn same_value(a: &str, b: &str) -> bool {
+    match (parse_number("value", a), parse_number("value", b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Parse a numeric attribute, or report a warning and ignore the
+/// attribute if it is malformed.
+fn read_number(args: &Args, key: &str, value: &str) -> Option<u64> {
+    match parse_number(key, value) {
+        Ok(number) => Some(number),
+        Err(e) => {
+            args.warn(e);
+            None
+        }
+    }
+}
+
+/// Like `read_number`, for attributes that must fit into 32 bits.
+fn read_u32(args: &Args, key: &str, value: &str) -> Option<u32> {
+    let number = read_number(args, key, value)?;
+    match u32::try_from(number) {
+        Ok(number) => Some(number),
+        Err(_) => {
+            args.warn(Error::invalid_value(key, value));
+            None
+        }
+    }
+}
+
+/// Split an attribute into its local name and its value, trimmed if sanitizing.
+fn split_attribute(args: &Args, attr: OwnedAttribute) -> (String, String) {
+    let OwnedAttribute { name, value } = attr;
+    let OwnedName { local_name, .. } = name;
+    let value = if args.sanitize {
+        String::from(value.trim())
+    } else {
+        value
+    };
+    (local_name, value)
+}
+
+fn check_endianness(args: &Args, attributes: Vec<OwnedAttribute>) -> Option<String> {
+    let mut f_type: Option<String> = None;
+    let mut f_value: Option<String> = None;
+    let mut f_id: Option<String> = None;
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "Type" => f_type = Some(value),
+            "Value" => f_value = Some(value),
+            "id" => f_id = Some(value),
+            _ => {}
+        }
+    }
+
+    f_type
+        .filter(|t| t == "stringfield")
+        .and(f_id.filter(|t| t == "Endianness"))
+        .and(f_value)
+}
+
+/// Open a TIXML file, skipping its unicode BOM if there is one.
+pub fn open_tixml_file<P: AsRef<Path>>(path: P) -> Result<File> {
+    let path = path.as_ref();
+    let mut fd_in = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+
+    // Some CCXML files contain unicode BOMs; these must be read to avoid
+    // XML parse errors.
+    let bom = Bom::from(&mut fd_in);
+    match bom {
+        Bom::Null | Bom::Utf8 => fd_in
+            .seek(SeekFrom::Start(bom.len() as u64))
+            .map_err(|e| Error::from(e).in_file(path))?,
+        _ => {
+            return Err(Error::UnsupportedEncoding {
+                context: Box::default(),
+                encoding: bom.to_string(),
+            }
+            .in_file(path))
+        }
+    };
+    Ok(fd_in)
+}
+
+/// Used by process_device_base to open each peripheral file and
+/// provide a xml parser for the file. It only makes sense to replace
+/// this if you wish to run this code without file-based storage.
+pub fn get_parser_from_filename(
+    root: &str,
+    filename: &str,
+) -> io::Result<xml::EventReader<std::fs::File>> {
+    let root_path = Path::new(root);
+    let concat_path = root_path.with_file_name(filename);
+    let fd_periph = File::open(&concat_path)?;
+    Ok(EventReader::new(fd_periph))
+}
+
+/// Copy the TIXML `<device>` attributes into the device.
+fn read_device_attributes(args: &Args, device: &mut Device, attributes: Vec<OwnedAttribute>) {
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "id" => device.name = value,
+            "description" => device.description = value,
+            _ => {}
+        }
+    }
+}
+
+/// Copy the TIXML `<cpu>` attributes into the device, and fill in
+/// what is known about the CPU.
+fn read_cpu_attributes(args: &Args, device: &mut Device, attributes: Vec<OwnedAttribute>) {
+    let mut f_isa = String::new();
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "HW_revision" => {
+                device.version = value.clone();
+                device.cpu.revision = value;
+            }
+            "isa" => {
+                device.cpu.name = if args.sanitize {
+                    value.replace("Cortex_", "C")
+                } else {
+                    value.clone()
+                };
+                f_isa = value;
+            }
+            _ => {}
+        }
+    }
+
+    apply_known_cpu(args, device, &f_isa);
+}
+
+/// The attributes of a TIXML `<instance>`.
+#[derive(Debug, Default)]
+pub(crate) struct InstanceAttributes {
+    pub(crate) id: Option<String>,
+    pub(crate) baseaddr: Option<String>,
+    pub(crate) size: Option<String>,
+    pub(crate) href: Option<String>,
+    pub(crate) xml: Option<String>,
+    pub(crate) xmlpath: Option<String>,
+}
+
+pub(crate) fn read_instance_attributes(
+    args: &Args,
+    attributes: Vec<OwnedAttribute>,
+) -> InstanceAttributes {
+    let mut instance = InstanceAttributes::default();
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "baseaddr" => instance.baseaddr = Some(value),
+            "endaddr" => (),
+            "size" => instance.size = Some(value),
+            "id" => {
+                instance.id = Some(if args.sanitize {
+                    value.replace('-', "_")
+                } else {
+                    value
+                })
+            }
+            "href" => instance.href = Some(value),
+            "xml" => instance.xml = Some(value),
+            "xmlpath" => instance.xmlpath = Some(value),
+            unknown => {
+                if args.verbose > 0 {
+                    eprintln!("Ignoring unknown key '{}' for 'instance'", unknown);
+                };
+            }
+        };
+    }
+    instance
+}
+
+/// Return the id and href of an instance, or why it is not converted.
+pub(crate) fn check_instance(
+    instance: &InstanceAttributes,
+) -> std::result::Result<(&str, &str), &'static str> {
+    // If no ID present, ignore the module (TI-internal?)
+    let id = instance.id.as_deref().ok_or("no id")?;
+    let href = match instance.href.as_deref() {
+        Some(href) if href.starts_with("../Modules/") => href,
+        Some(_) => return Err("href does not start with ../Modules/"),
+        None => return Err("no href"),
+    };
+    if id == "Cp15" || id == "Vfp" {
+        return Err("co-processor registers");
+    }
+    Ok((id, href))
+}
+
+/// Read the peripheral referenced by a TIXML `<instance>`, or return
+/// `None` if the instance should be skipped. Peripheral files already
+/// read are taken from `modules`, by `href`.
+fn read_instance(
+    args: &Args,
+    attributes: Vec<OwnedAttribute>,
+    fname2parser: &dyn Fn(&str) -> io::Result<xml::EventReader<std::fs::File>>,
+    modules: &mut HashMap<String, Peripheral>,
+) -> Result<Option<Peripheral>> {
+    let instance = read_instance_attributes(args, attributes);
+    let (id, href) = match check_instance(&instance) {
+        Ok((id, href)) => (id.to_string(), href.to_string()),
+        Err(reason) => {
+            if let Some(ref id) = instance.id {
+                eprintln!("Skipping peripheral {:?}: {}", id, reason);
+            }
+            return Ok(None);
+        }
+    };
+
+    let mut peripheral = match modules.get(&href) {
+        Some(peripheral) => peripheral.clone(),
+        None => {
+            if !args.silent {
+                eprintln!("Processing peripheral file: {:?}", &href);
+            }
+            let warnings = args.warning_count();
+            let peripheral = fname2parser(&href)
+                .map_err(Error::from)
+                .and_then(|parser| read_peripheral(args, parser));
+            args.locate_warnings(warnings, |w| w.in_file(&href).in_peripheral(&id));
+            let peripheral = peripheral.map_err(|e| e.in_file(&href).in_peripheral(&id))?;
+            modules.insert(href.clone(), peripheral.clone());
+            peripheral
+        }
+    };
+    peripheral.base_address = match instance.baseaddr {
+        // An instance without its address is of no use, so skip it
+        Some(baseaddr) => match parse_number("baseaddr", &baseaddr) {
+            Ok(baseaddr) => Some(baseaddr),
+            Err(e) => {
+                args.warn(e.in_peripheral(&id));
+                return Ok(None);
+            }
+        },
+        None => None,
+    };
+    peripheral.name = id;
+
+    // The module path, such as `msp430/USCI_A0__UART_Mode_2`
+    let module = match (instance.xmlpath, instance.xml) {
+        (Some(xmlpath), Some(xml)) => xmlpath + &xml,
+        _ => href,
+    };
+    let module = module
+        .trim_start_matches("../Modules/")
+        .trim_end_matches(".xml");
+    peripheral.group_name = group_name(args, module);
+    peripheral.module = Some(module.to_string());
+
+    peripheral.size = match instance.size {
+        Some(size) => match parse_number("size", &size) {
+            Ok(size) => Some(size),
+            Err(e) => {
+                args.warn(e.in_peripheral(&peripheral.name));
+                None
+            }
+        },
+        None => {
+            args.warn(
+                Error::MissingValue {
+                    context: Box::default(),
+                    key: "size".to_string(),
+                }
+                .in_peripheral(&peripheral.name),
+            );
+            None
+        }
+    };
+    Ok(Some(peripheral))
+}
+
+/// A `<cpu>` element of a TIXML device.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct CpuInfo {
+    /// Index of the CPU, as selected by `cpunum`
+    pub index: u32,
+    pub id: String,
+    pub isa: String,
+    pub description: String,
+}
+
+pub(crate) fn read_cpu_info(args: &Args, index: u32, attributes: Vec<OwnedAttribute>) -> CpuInfo {
+    let mut cpu = CpuInfo {
+        index,
+        ..CpuInfo::default()
+    };
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        match attr_name.as_ref() {
+            "id" => cpu.id = value,
+            "isa" => cpu.isa = value,
+            "description" => cpu.description = value,
+            _ => {}
+        }
+    }
+    cpu
+}
+
+/// List the CPUs of a TIXML device.
+pub fn read_cpus<I>(args: &Args, parser: xml::EventReader<I>) -> Result<Vec<CpuInfo>>
+where
+    I: io::Read,
+{
+    let mut cpus = vec![];
+    for e in parser {
+        match e {
+            Ok(StartElement {
+                name, attributes, ..
+            }) if name.local_name == "cpu" => {
+                let index = cpus.len() as u32;
+                cpus.push(read_cpu_info(args, index, attributes));
+            }
+            Err(e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(cpus)
+}
+
+/// Read a TIXML device into the device model.
+pub fn read_device<I>(
+    args: &Args,
+    parser: xml::EventReader<I>,
+    fname2parser: &dyn Fn(&str) -> io::Result<xml::EventReader<std::fs::File>>,
+) -> Result<Device>
+where
+    I: io::Read,
+{
+    let mut parser = parser;
+    let mut device = Device::default();
+    let mut in_cpu_tag = false;
+    let mut cpunum = 0;
+    let selected_cpunum = args.cpunum;
+    let mut endianness: Option<String> = None;
+    let mut modules = HashMap::new();
+
+    loop {
+        let e = parser.next();
+        let position = parser.position();
+        match e {
+            Ok(StartElement {
+                name, attributes, ..
+            }) => {
+                if args.verbose > 0 {
+                    eprintln!("Processing StartElement: {}", name);
+                }
+                let warnings = args.warning_count();
+                let OwnedName { local_name, .. } = name;
+                match local_name.as_ref() {
+                    "device" => {
+                        read_device_attributes(args, &mut device, attributes);
+                    }
+                    "cpu" => {
+                        in_cpu_tag = true;
+                        if cpunum == selected_cpunum {
+                            read_cpu_attributes(args, &mut device, attributes);
+                        }
+                    }
+                    "property" => {
+                        if !in_cpu_tag || (cpunum != selected_cpunum) {
+                            continue;
+                        }
+
+                        endianness = endianness.or_else(|| check_endianness(args, attributes));
+                    }
+                    "instance" => {
+                        if !in_cpu_tag || (cpunum != selected_cpunum) {
+                            if args.verbose > 0 {
+                                eprintln!(
+                                    "Skipping cpu instance; in_cpu_tag='{}', cpunum='{}'",
+                                    in_cpu_tag, cpunum
+                                );
+                            }
+                            continue;
+                        }
+
+                        if let Some(peripheral) =
+                            read_instance(args, attributes, fname2parser, &mut modules)
+                                .map_err(|e| e.at(position))?
+                        {
+                            device.peripherals.push(peripheral);
+                        }
+                    }
+                    unknown => {
+                        if args.verbose > 0 {
+                            eprintln!("Ignoring unknown start element key '{}'", unknown);
+                        }
+                    }
+                }
+                args.locate_warnings(warnings, |w| w.at(position));
+            }
+
+            Ok(EndElement { name }) => {
+                if args.verbose > 0 {
+                    eprintln!("Processing EndElement: {}", name);
+                }
+                let OwnedName { local_name, .. } = name;
+                match local_name.as_ref() {
+                    "device" | "instance" => {}
+                    "cpu" => {
+                        if cpunum == selected_cpunum {
+                            if let Some(endian) = endianness.take() {
+                                device.cpu.endian = endian;
+                            }
+                        }
+
+                        in_cpu_tag = false;
+                        cpunum += 1;
+                    }
+                    unknown => {
+                        if args.verbose > 0 {
+                            eprintln!("Ignoring unknown end element key '{}'", unknown);
+                        }
+                    }
+                }
+            }
+
+            Ok(EndDocument) => break,
+            Err(e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(device)
+}
+
+/// Copy the TIXML `<module>` attributes into the peripheral.
+fn read_module_attributes(
+    args: &Args,
+    peripheral: &mut Peripheral,
+    attributes: Vec<OwnedAttribute>,
+) {
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        match attr_name.as_ref() {
+            "HW_revision" => (),
+            "XML_version" => (),
+            "noNamespaceSchemaLocation" => (),
+            "id" => peripheral.name = value,
+            "value" => {
+                if args.peripheral_only {
+                    peripheral.value = Some(value)
+                }
+            }
+            "token" => (),
+            "description" => peripheral.description = Some(value),
+            unknown => {
+                if args.verbose > 0 {
+                    eprintln!("Ignoring unknown key '{}' for 'module'", unknown);
+                };
+            }
+        };
+    }
+}
+
+/// Read a register, or return `None` if its offset is invalid.
+fn read_register(
+    args: &Args,
+    attributes: Vec<OwnedAttribute>,
+    used_registers: &mut Option<HashSet<String>>,
+) -> Option<Register> {
+    let mut register = Register::default();
+    // Assume access is read-write if not specified, and let further restrictions be applied by the bitfilelds
+    let mut f_rwaccess = "RW".to_string();
+    let mut f_offset: Option<String> = None;
+    let mut f_width: Option<String> = None;
+    let mut f_reset_value: Option<String> = None;
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "id" => register.name = value,
+            "value" => register.value = Some(value),
+            "width" => f_width = Some(value),
+            "acronym" => (),
+            "description" => register.description = Some(value),
+            "rwaccess" => f_rwaccess = value,
+            "offset" => f_offset = Some(value),
+            "resetval" => f_reset_value = Some(value),
+            unknown => {
+                if args.verbose > 0 {
+                    eprintln!("Ignoring unknown key '{}' for 'register'", unknown);
+                };
+            }
+        };
+    }
+
+    if let Some(offset) = f_offset {
+        match parse_number("offset", &offset) {
+            Ok(offset) => register.address_offset = offset,
+            Err(e) => {
+                args.warn(e.in_register(&register.name));
+                return None;
+            }
+        }
+    }
+    register.size = f_width.and_then(|w| read_u32(args, "width", &w));
+    register.reset_value = f_reset_value.and_then(|r| read_number(args, "resetval", &r));
+    if register.description.is_none() && !register.name.is_empty() {
+        register.description = Some(register.name.clone());
+    }
+    if let Some(ref mut used_registers) = used_registers {
+        while !used_registers.insert(register.name.clone()) {
+            args.warn(
+                Warning::DuplicateRegister {
+                    context: Box::default(),
+                    name: register.name.clone(),
+                }
+                .in_register(&register.name),
+            );
+            register.name.push('_');
+        }
+    }
+    let (access, modified_write_values, read_action) = read_access(args, &f_rwaccess);
+    register.access = access;
+    register.modified_write_values = modified_write_values;
+    register.read_action = read_action;
+    Some(register)
+}
+
+fn in_field(error: Error, name: &Option<String>) -> Error {
+    match name {
+        Some(name) => error.in_field(name),
+        None => error,
+    }
+}
+
+/// Read a bitfield, adding its reset value to that of the register.
+fn read_field(
+    args: &Args,
+    attributes: Vec<OwnedAttribute>,
+    mut register: Option<&mut Register>,
+) -> Result<Field> {
+    let mut f_name: Option<String> = None;
+    let mut f_range: Option<String> = None;
+    let mut f_begin: Option<u32> = None;
+    let mut f_width: Option<u32> = None;
+    let mut f_end: Option<u32> = None;
+    let mut f_rwaccess: Option<String> = None;
+    let mut f_description: Option<String> = None;
+    let mut f_reset_value: Option<u64> = None;
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "id" => f_name = Some(value),
+            "range" => f_range = Some(value),
+            "begin" => f_begin = read_u32(args, "begin", &value),
+            "width" => f_width = read_u32(args, "width", &value),
+            "end" => f_end = read_u32(args, "end", &value),
+            "rwaccess" => f_rwaccess = Some(value),
+            "description" => f_description = Some(value),
+            "resetval" => {
+                f_reset_value = read_number(args, "resetval", &value);
+            }
+            unknown => {
+                if args.verbose > 0 {
+                    eprintln!("Ignoring unknown key '{}' for 'bitfield'", unknown);
+                };
+            }
+        };
+    }
+
+    if let Some(end_int) = f_end {
+        // Trust f_begin more than f_width
+        match f_begin {
+            Some(begin_int) if begin_int >= end_int => f_width = Some(begin_int - end_int + 1),
+            Some(begin_int) => args.warn(Error::invalid_value("begin", &begin_int.to_string())),
+            None => {}
+        }
+
+        if let Some(reset_value) = f_reset_value {
+            let reg_width: u32 = register.as_ref().and_then(|r| r.size).unwrap_or(32);
+
+            if end_int < reg_width {
+                let overflow = reset_value.checked_shr(reg_width - end_int).unwrap_or(0);
+                if overflow == 0 {
+                    if let Some(ref mut register) = register {
+                        let shifted_reset_value = reset_value.checked_shl(end_int).unwrap_or(0);
+                        register.reset_value =
+                            Some(register.reset_value.unwrap_or(0) | shifted_reset_value);
+                    }
+                } else if args.sanitize {
+                    args.warn(Error::ResetValueTooBig {
+                        context: Box::default(),
+                        reset_value,
+                    });
+                } else {
+                    let error = Error::ResetValueTooBig {
+                        context: Box::default(),
+                        reset_value,
+                    };
+                    return Err(in_field(error, &f_name));
+                }
+            }
+        }
+    }
+
+    if f_name.is_none() && args.sanitize {
+        if let Some(description) = f_description.clone() {
+            f_name = Some(get_name_from_description(&description));
+        } else {
+            let parent_reg_name = register.as_ref().map(|r| r.name.as_str());
+            f_name = Some(format!(
+                "{}_W{}_O{}",
+                parent_reg_name.unwrap_or_default(),
+                f_width.unwrap_or_default(),
+                f_end.unwrap_or_default()
+            ));
+        }
+    }
+
+    let (access, modified_write_values, read_action) = match f_rwaccess {
+        Some(ti_access) => read_access(args, &ti_access),
+        None => (None, None, None),
+    };
+    Ok(Field {
+        name: f_name.unwrap_or_default(),
+        description: f_description,
+        bit_offset: f_end,
+        bit_width: f_width,
+        // bitRange unlikely to work with svd2rust
+        bit_range: if args.sanitize { None } else { f_range },
+        access,
+        modified_write_values,
+        read_action,
+        enumerated_values_name: None,
+        enumerated_values_derived_from: None,
+        enumerated_values: vec![],
+        write_enumerated_values: vec![],
+    })
+}
+
+/// Read a bitenum, or return `None` if it has no value.
+fn read_enumerated_value(args: &Args, attributes: Vec<OwnedAttribute>) -> Option<EnumeratedValue> {
+    let mut f_id: Option<String> = None;
+    let mut f_value: Option<String> = None;
+    let mut f_description: Option<String> = None;
+
+    for attr in attributes {
+        let (attr_name, value) = split_attribute(args, attr);
+        if value.is_empty() {
+            continue;
+        }
+        match attr_name.as_ref() {
+            "id" => f_id = Some(value),
+            "value" => f_value = Some(value),
+            "description" => f_description = Some(value),
+            "token" => (),
+            unknown => {
+                if args.verbose > 0 {
+                    eprintln!("Ignoring unknown key '{}' for 'bitenum'", unknown);
+                };
+            }
+        };
+    }
+
+    let value = f_value?;
+
+    let name = match f_id {
+        Some(id) => id,
+        // If id is missing, use value instead
+        None if args.sanitize => value.clone(),
+        None => String::new(),
+    };
+    Some(EnumeratedValue {
+        name,
+        description: f_description,
+        value,
+    })
+}
+
+/// Write the bitenum values of a field as hex numbers relative to the
+/// field. Some TI files give the register value instead, with the field
+/// bits in place; if no value fits into the field otherwise, they are
+/// all shifted down to the field.
+fn normalize_enum_values(args: &Args, field: &mut Field) {
+    let numbers: Vec<Option<u64>> = field
+        .enumerated_values
+        .iter()
+        .map(|value| read_number(args, "value", &value.value))
+        .collect();
+    let shift = match (field.bit_offset, field.bit_width) {
+        (Some(offset), Some(width)) if offset > 0 && offset < 64 && width < 64 => {
+            let fits = |number: u64| number >> width == 0;
+            let mut numbers = numbers.iter().flatten();
+            let register_values = numbers
+                .clone()
+                .all(|&number| number.trailing_zeros() >= offset && fits(number >> offset));
+            if register_values && numbers.any(|&number| !fits(number)) {
+                offset
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    };
+    for (value, number) in field.enumerated_values.iter_mut().zip(numbers) {
+        if let Some(number) = number {
+            if number >> shift != number {
+                args.warn(Warning::EnumValueShifted {
+                    context: Box::default(),
+                    value: value.value.clone(),
+                    shifted: number >> shift,
+                });
+            }
+            value.value = format!("0x{:X}", number >> shift);
+        }
+    }
+}
+
+/// Read a TIXML peripheral into the device model.
+pub fn read_peripheral<I>(args: &Args, parser: xml::EventReader<I>) -> Result<Peripheral>
+where
+    I: io::Read,
+{
+    let mut parser = parser;
+    let mut peripheral = Peripheral::default();
+    let mut register: Option<Register> = None;
+    let mut field: Option<Field> = None;
+
+    let mut f_used_registers = None;
+
+    loop {
+        let e = parser.next();
+        let position = parser.position();
+        match e {
+            Ok(StartElement {
+                name, attributes, ..
+            }) => {
+                if args.verbose > 0 {
+                    eprintln!("Processing StartElement: {}", name);
+                }
+                let warnings = args.warning_count();
+                let OwnedName { local_name, .. } = name;
+                match local_name.as_ref() {
+                    "module" => {
+                        if args.sanitize {
+                            f_used_registers = Some(HashSet::new());
+                        }
+                        read_module_attributes(args, &mut peripheral, attributes);
+                    }
+                    "register" => {
+                        // The fields of a skipped register are skipped too
+                        register = read_register(args, attributes, &mut f_used_registers);
+                    }
+                    "bitfield" => {
+                        field = Some(read_field(args, attributes, register.as_mut()).map_err(
+                            |e| match register {
+                                Some(ref register) => e.in_register(&register.name).at(position),
+                                None => e.at(position),
+                            },
+                        )?);
+                    }
+                    "bitenum" => {
+                        if let Some(value) = read_enumerated_value(args, attributes) {
+                            if let Some(ref mut field) = field {
+                                field.enumerated_values.push(value);
+                            }
+                        }
+                    }
+                    unknown => {
+                        if args.verbose > 0 {
+                            eprintln!("Ignoring unknown start element key '{}'", unknown);
+                        }
+                    }
+                };
+                args.locate_warnings(warnings, |w| {
+                    let w = match field {
+                        Some(ref field) if !field.name.is_empty() => w.in_field(&field.name),
+                        _ => w,
+                    };
+                    let w = match register {
+                        Some(ref register) => w.in_register(&register.name),
+                        None => w,
+                    };
+                    w.at(position)
+                });
+            }
+            Ok(EndElement { name }) => {
+                if args.verbose > 0 {
+                    eprintln!("Processing EndElement: {}", name);
+                }
+                let OwnedName { local_name, .. } = name;
+                match local_name.as_ref() {
+                    "module" => {
+                        f_used_registers = None;
+                    }
+                    "register" => {
+                        if let Some(register) = register.take() {
+                            peripheral.registers.push(register);
+                        }
+                    }
+                    "bitfield" => {
+                        if let (Some(mut field), Some(ref mut register)) =
+                            (field.take(), &mut register)
+                        {
+                            let warnings = args.warning_count();
+                            normalize_enum_values(args, &mut field);
+                            args.locate_warnings(warnings, |w| {
+                                w.in_field(&field.name).in_register(&register.name)
+                            });
+                            register.fields.push(field);
+                        }
+                    }
+                    "bitenum" => {}
+                    unknown => {
+                        if args.verbose > 0 {
+                            eprintln!("Ignoring unknown end element key '{}'", unknown);
+                        }
+                    }
+                };
+            }
+            Ok(EndDocument) => break,
+            Err(e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(peripheral)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> Args {
+        Args::new(true, 0, false, false, false, 0)
+    }
+
+    fn field(offset: u32, width: u32, values: &[&str]) -> Field {
+        Field {
+            name: "MODE".to_string(),
+            bit_offset: Some(offset),
+            bit_width: Some(width),
+            enumerated_values: values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| EnumeratedValue {
+                    name: format!("V{}", i),
+                    description: None,
+                    value: value.to_string(),
+                })
+                .collect(),
+            ..Field::default()
+        }
+    }
+
+    fn values(field: &Field) -> Vec<&str> {
+        field
+            .enumerated_values
+            .iter()
+            .map(|value| value.value.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn skip_register_with_invalid_offset() {
+        let args = args();
+        let xml = r#"<module id="GPIO">
+            <register id="DIN" offset="0xZZ" width="32">
+                <bitfield id="PIN0" begin="0" end="0" width="1" rwaccess="R"/>
+            </register>
+            <register id="DOUT" offset="0x4" width="32"/>
+        </module>"#;
+        let peripheral = read_peripheral(&args, EventReader::new(xml.as_bytes())).unwrap();
+        let names: Vec<&str> = peripheral
+            .registers
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect();
+        assert_eq!(names, ["DOUT"]);
+        let warnings = args.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].context().register.as_deref(), Some("DIN"));
+        assert_eq!(warnings[0].context().line, Some(2));
+    }
+
+    #[test]
+    fn parse_decimal() {
+        assert_eq!(parse_number("value", "0").unwrap(), 0);
+        assert_eq!(parse_number("value", "42").unwrap(), 42);
+    }
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(parse_number("value", "0x1F").unwrap(), 31);
+        assert_eq!(parse_number("value", "0X1f").unwrap(), 31);
+    }
+
+    #[test]
+    fn parse_binary() {
+        assert_eq!(parse_number("value", "0b101").unwrap(), 5);
+        assert_eq!(parse_number("value", "0B11").unwrap(), 3);
+        assert_eq!(parse_number("value", "#b0110").unwrap(), 6);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(parse_number("value", "").is_err());
+        assert!(parse_number("value", "0x").is_err());
+        assert!(parse_number("value", "0b102").is_err());
+        assert!(parse_number("value", "ENABLE").is_err());
+    }
+
+    #[test]
+    fn normalize_writes_hex() {
+        let args = args();
+        let mut field = field(0, 4, &["10", "0b11", "#b1", "0xf"]);
+        normalize_enum_values(&args, &mut field);
+        assert_eq!(values(&field), ["0xA", "0x3", "0x1", "0xF"]);
+        assert!(args.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn normalize_shifts_register_values() {
+        let args = args();
+        let mut field = field(4, 2, &["0x00", "0x10", "0x20", "0x30"]);
+        normalize_enum_values(&args, &mut field);
+        assert_eq!(values(&field), ["0x0", "0x1", "0x2", "0x3"]);
+        assert_eq!(args.take_warnings().len(), 3);
+    }
+
+    #[test]
+    fn normalize_keeps_relative_values() {
+        // All aligned to the offset, but all fitting into the field too
+        let args = args();
+        let mut field = field(1, 3, &["0", "2", "4", "6"]);
+        normalize_enum_values(&args, &mut field);
+        assert_eq!(values(&field), ["0x0", "0x2", "0x4", "0x6"]);
+        assert!(args.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn normalize_keeps_mixed_values() {
+        // Not all values are register values, so none is shifted
+        let args = args();
+        let mut field = field(4, 2, &["0x1", "0x20"]);
+        normalize_enum_values(&args, &mut field);
+        assert_eq!(values(&field), ["0x1", "0x20"]);
+        assert!(args.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn normalize_keeps_invalid_values() {
+        let args = args();
+        let mut field = field(4, 2, &["0x10", "ON"]);
+        normalize_enum_values(&args, &mut field);
+        assert_eq!(values(&field), ["0x1", "ON"]);
+        assert_eq!(args.take_warnings().len(), 2);
+    }
+}
@@ -0,0 +1,427 @@
+//! Cleaning up the device model for SVD consumers such as svd2rust.
+//!
+//! This runs after patches are applied, so that a patch can still fix
+//! what sanitizing would otherwise throw away.
+
+use crate::derive::derive_field_enumerated_values;
+use crate::error::Warning;
+use crate::model::{Access, EnumeratedValue, Field, Peripheral};
+use crate::reader::parse_number;
+use crate::Args;
+
+/// Fix the enumerated values of a field that do not fit into it. Sets of
+/// register values, with the field bits in place, are already shifted
+/// into the field when reading; of the values left, one that is such a
+/// register value is shifted into the field too, unless its shifted value
+/// is already used. The others are most likely meant for a wider field,
+/// and are dropped. Returns whether any value was dropped.
+fn fix_oversize_enum_values(field: &mut Field, warnings: &mut Vec<Warning>) -> bool {
+    let (offset, width) = match (field.bit_offset, field.bit_width) {
+        (Some(offset), Some(width)) if width < 64 => (offset.min(64), width),
+        _ => return false,
+    };
+    let fits = |number: u64| number >> width == 0;
+    let numbers: Vec<Option<u64>> = field
+        .enumerated_values
+        .iter()
+        .map(|value| parse_number("value", &value.value).ok())
+        .collect();
+    let name = &field.name;
+    let count = field.enumerated_values.len();
+    let mut kept = vec![];
+    for (mut value, number) in field.enumerated_values.drain(..).zip(&numbers) {
+        let number = match *number {
+            Some(number) if !fits(number) => number,
+            _ => {
+                kept.push(value);
+                continue;
+            }
+        };
+        let shifted = number.checked_shr(offset).unwrap_or(0);
+        let register_value = offset > 0
+            && number.trailing_zeros() >= offset
+            && fits(shifted)
+            && !numbers.contains(&Some(shifted));
+        if register_value {
+            warnings.push(
+                Warning::EnumValueShifted {
+                    context: Box::default(),
+                    value: value.value.clone(),
+                    shifted,
+                }
+                .in_field(name),
+            );
+            value.value = format!("0x{:X}", shifted);
+            kept.push(value);
+        } else {
+            warnings.push(
+                Warning::EnumValueDropped {
+                    context: Box::default(),
+                    value: value.value,
+                    bit_width: width,
+                }
+                .in_field(name),
+            );
+        }
+    }
+    field.enumerated_values = kept;
+    field.enumerated_values.len() != count
+}
+
+/// What sanitizing does with an enumerated value whose value is already
+/// used in its field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumDuplicates {
+    /// Leave it out, with a warning
+    Drop,
+    /// Add its name to that of the first value, such as `IDLE_OR_NONE`
+    Merge,
+    /// Keep it, with a suffix counting the earlier values, such as
+    /// `NONE_1`
+    Rename,
+    /// Leave it out, but name it in the description of the first value
+    Alias,
+    /// In a read-write field, take a value given twice with two names as
+    /// its names for reading and writing, such as `PENDING` and `CLEAR`;
+    /// leave out other duplicates, with a warning
+    Split,
+}
+
+/// Add a note about a merged value to a description.
+fn add_note(value: &mut EnumeratedValue, alias: &EnumeratedValue, note: &str) {
+    let note = match alias.description {
+        Some(ref description) if !description.is_empty() => {
+            format!("{} {}: {}", note, alias.name, description)
+        }
+        _ => format!("{} {}", note, alias.name),
+    };
+    value.description = Some(match value.description.take() {
+        Some(description) if !description.is_empty() => format!("{} ({})", description, note),
+        _ => note,
+    });
+}
+
+/// Handle the enumerated values of a field whose value is already used,
+/// as selected by `duplicates`. When a pair of values is split, the field
+/// gets separate values for reading and writing.
+fn drop_duplicate_enum_values(
+    field: &mut Field,
+    access: Access,
+    duplicates: EnumDuplicates,
+) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut read: Vec<EnumeratedValue> = vec![];
+    let mut write: Vec<Option<EnumeratedValue>> = vec![];
+    let count = |value: &str, values: &[EnumeratedValue]| {
+        values.iter().filter(|v| v.value == value).count()
+    };
+    let pairs: Vec<bool> = field
+        .enumerated_values
+        .iter()
+        .map(|v| count(&v.value, &field.enumerated_values) == 2)
+        .collect();
+    for (mut value, pair) in field.enumerated_values.drain(..).zip(pairs) {
+        let i = match read.iter().position(|r| r.value == value.value) {
+            None => {
+                read.push(value);
+                write.push(None);
+                continue;
+            }
+            Some(i) => i,
+        };
+        let same_name = read[i].name == value.name;
+        match duplicates {
+            EnumDuplicates::Split if access == Access::ReadWrite && pair && !same_name => {
+                warnings.push(
+                    Warning::EnumValueSplit {
+                        context: Box::default(),
+                        value: value.value.clone(),
+                    }
+                    .in_field(&field.name),
+                );
+                write[i] = Some(value);
+            }
+            EnumDuplicates::Drop | EnumDuplicates::Split => warnings.push(
+                Warning::DuplicateEnumValue {
+                    context: Box::default(),
+                    value: value.value,
+                }
+                .in_field(&field.name),
+            ),
+            EnumDuplicates::Merge if same_name => {}
+            EnumDuplicates::Merge => {
+                let first = &mut read[i];
+                first.name = format!("{}_OR_{}", first.name, value.name);
+                add_note(first, &value, "merged with");
+            }
+            EnumDuplicates::Rename => {
+                value.name = format!("{}_{}", value.name, count(&value.value, &read));
+                read.push(value);
+                write.push(None);
+            }
+            EnumDuplicates::Alias if same_name => {}
+            EnumDuplicates::Alias => add_note(&mut read[i], &value, "alias"),
+        }
+    }
+    if write.iter().any(Option::is_some) {
+        field.write_enumerated_values = read
+            .iter()
+            .zip(write)
+            .map(|(r, w)| w.unwrap_or_else(|| r.clone()))
+            .collect();
+    }
+    field.enumerated_values = read;
+    warnings
+}
+
+/// Sanitize the registers of a peripheral, reporting what is changed or
+/// dropped as warnings.
+pub(crate) fn sanitize_peripheral(args: &Args, peripheral: &mut Peripheral) {
+    let mut dropped = vec![];
+    for (r, register) in peripheral.registers.iter_mut().enumerate() {
+        let mut warnings = vec![];
+        for (f, field) in register.fields.iter_mut().enumerate() {
+            if fix_oversize_enum_values(field, &mut warnings) {
+                dropped.push((r, f));
+            }
+            // Without a device header, the default access is read-write
+            let access = field
+                .access
+                .or(register.access)
+                .unwrap_or(Access::ReadWrite);
+            warnings.extend(drop_duplicate_enum_values(
+                field,
+                access,
+                args.enum_duplicates,
+            ));
+        }
+        for warning in warnings {
+            args.warn(
+                warning
+                    .in_register(&register.name)
+                    .in_peripheral(&peripheral.name),
+            );
+        }
+    }
+    // What is left of an oversize enumeration may be one that is already
+    // there, which svd2rust then generates only once
+    for (r, f) in dropped {
+        if let Some(path) = derive_field_enumerated_values(peripheral, r, f) {
+            let register = &peripheral.registers[r];
+            args.warn(
+                Warning::EnumValuesDerived {
+                    context: Box::default(),
+                    path,
+                }
+                .in_field(&register.fields[f].name)
+                .in_register(&register.name)
+                .in_peripheral(&peripheral.name),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Register;
+
+    fn field(name: &str, offset: u32, width: u32, values: &[(&str, &str)]) -> Field {
+        Field {
+            name: name.to_string(),
+            bit_offset: Some(offset),
+            bit_width: Some(width),
+            enumerated_values: values
+                .iter()
+                .map(|(name, value)| EnumeratedValue {
+                    name: name.to_string(),
+                    description: None,
+                    value: value.to_string(),
+                })
+                .collect(),
+            ..Field::default()
+        }
+    }
+
+    fn values(values: &[EnumeratedValue]) -> Vec<(&str, &str)> {
+        values
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn shift_register_value() {
+        let mut field = field("MODE", 4, 2, &[("A", "0x1"), ("B", "0x20")]);
+        let mut warnings = vec![];
+        assert!(!fix_oversize_enum_values(&mut field, &mut warnings));
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("A", "0x1"), ("B", "0x2")]
+        );
+        assert!(matches!(
+            warnings[..],
+            [Warning::EnumValueShifted { shifted: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn drop_value_whose_shifted_value_is_used() {
+        let mut field = field("MODE", 4, 2, &[("A", "0x2"), ("B", "0x20")]);
+        let mut warnings = vec![];
+        assert!(fix_oversize_enum_values(&mut field, &mut warnings));
+        assert_eq!(values(&field.enumerated_values), [("A", "0x2")]);
+        assert!(matches!(
+            warnings[..],
+            [Warning::EnumValueDropped { bit_width: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn drop_value_of_wider_field() {
+        let mut field = field("MODE", 4, 2, &[("A", "0x1"), ("B", "0x5"), ("C", "0x3")]);
+        let mut warnings = vec![];
+        assert!(fix_oversize_enum_values(&mut field, &mut warnings));
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("A", "0x1"), ("C", "0x3")]
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn keep_fitting_values() {
+        let mut field = field("MODE", 0, 2, &[("A", "0x0"), ("B", "0x3"), ("C", "X")]);
+        let mut warnings = vec![];
+        assert!(!fix_oversize_enum_values(&mut field, &mut warnings));
+        assert_eq!(field.enumerated_values.len(), 3);
+        assert!(warnings.is_empty());
+    }
+
+    fn duplicates(duplicates: EnumDuplicates, access: Access) -> (Field, Vec<Warning>) {
+        let mut field = field(
+            "EN",
+            0,
+            1,
+            &[("DISABLE", "0x0"), ("ENABLE", "0x1"), ("ON", "0x1")],
+        );
+        let warnings = drop_duplicate_enum_values(&mut field, access, duplicates);
+        (field, warnings)
+    }
+
+    #[test]
+    fn drop_duplicate() {
+        let (field, warnings) = duplicates(EnumDuplicates::Drop, Access::ReadWrite);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE", "0x1")]
+        );
+        assert!(field.write_enumerated_values.is_empty());
+        assert!(matches!(warnings[..], [Warning::DuplicateEnumValue { .. }]));
+    }
+
+    #[test]
+    fn merge_duplicate() {
+        let (field, warnings) = duplicates(EnumDuplicates::Merge, Access::ReadWrite);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE_OR_ON", "0x1")]
+        );
+        assert!(field.write_enumerated_values.is_empty());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn rename_duplicate() {
+        let (field, warnings) = duplicates(EnumDuplicates::Rename, Access::ReadWrite);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE", "0x1"), ("ON_1", "0x1")]
+        );
+        assert!(field.write_enumerated_values.is_empty());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn alias_duplicate() {
+        let (field, warnings) = duplicates(EnumDuplicates::Alias, Access::ReadWrite);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE", "0x1")]
+        );
+        assert!(field.enumerated_values[1]
+            .description
+            .as_deref()
+            .is_some_and(|d| d.contains("ON")));
+        assert!(field.write_enumerated_values.is_empty());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn split_read_write_pair() {
+        let (field, warnings) = duplicates(EnumDuplicates::Split, Access::ReadWrite);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE", "0x1")]
+        );
+        assert_eq!(
+            values(&field.write_enumerated_values),
+            [("DISABLE", "0x0"), ("ON", "0x1")]
+        );
+        assert!(matches!(warnings[..], [Warning::EnumValueSplit { .. }]));
+    }
+
+    #[test]
+    fn drop_duplicate_of_read_only_field() {
+        let (field, warnings) = duplicates(EnumDuplicates::Split, Access::ReadOnly);
+        assert_eq!(
+            values(&field.enumerated_values),
+            [("DISABLE", "0x0"), ("ENABLE", "0x1")]
+        );
+        assert!(field.write_enumerated_values.is_empty());
+        assert!(matches!(warnings[..], [Warning::DuplicateEnumValue { .. }]));
+    }
+
+    #[test]
+    fn drop_more_than_a_pair() {
+        let values_in = [("A", "0x1"), ("B", "0x1"), ("C", "0x1")];
+        let mut field = field("MODE", 0, 1, &values_in);
+        let warnings =
+            drop_duplicate_enum_values(&mut field, Access::ReadWrite, EnumDuplicates::Split);
+        assert_eq!(values(&field.enumerated_values), [("A", "0x1")]);
+        assert!(field.write_enumerated_values.is_empty());
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn derive_what_is_left() {
+        let args = Args::new(true, 0, false, true, false, 0);
+        let enable = [("DIS", "0x0"), ("EN", "0x1")];
+        let mut peripheral = Peripheral {
+            name: "GPIO".to_string(),
+            registers: vec![Register {
+                name: "CTL".to_string(),
+                fields: vec![
+                    field("EN0", 0, 1, &enable),
+                    field(
+                        "EN1",
+                        1,
+                        1,
+                        &[("DIS", "0x0"), ("EN", "0x1"), ("ALL", "0x3")],
+                    ),
+                ],
+                ..Register::default()
+            }],
+            ..Peripheral::default()
+        };
+        sanitize_peripheral(&args, &mut peripheral);
+        let fields = &peripheral.registers[0].fields;
+        assert_eq!(values(&fields[1].enumerated_values), enable);
+        assert_eq!(
+            fields[1].enumerated_values_derived_from.as_deref(),
+            Some("CTL.EN0.EN0")
+        );
+        assert_eq!(fields[0].enumerated_values_name.as_deref(), Some("EN0"));
+        assert_eq!(args.take_warnings().len(), 2);
+    }
+}
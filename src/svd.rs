@@ -0,0 +1,548 @@
+//! Writing the device model as SVD.
+
+use std::io;
+
+use xml::writer;
+
+use crate::error::Result;
+use crate::model::{
+    Access, Device, EnumeratedValue, Field, Interrupt, ModifiedWriteValues, Peripheral, ReadAction,
+    Register, Usage,
+};
+use crate::Args;
+
+fn write_event<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    what: &str,
+    event: writer::XmlEvent,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    if args.verbose > 2 {
+        eprintln!("Writing {}: {:?}", what, event);
+    }
+    Ok(xml_out.write(event)?)
+}
+
+pub(crate) fn write_start<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    element: &str,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_event(
+        args,
+        xml_out,
+        "start-tag",
+        writer::XmlEvent::start_element(element).into(),
+    )
+}
+
+/// Start an element that may be derived from another one.
+pub(crate) fn write_start_derived<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    element: &str,
+    derived_from: Option<&str>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    let start = writer::XmlEvent::start_element(element);
+    let start = match derived_from {
+        Some(derived_from) => start.attr("derivedFrom", derived_from),
+        None => start,
+    };
+    write_event(args, xml_out, "start-tag", start.into())
+}
+
+pub(crate) fn write_comment<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    data: &str,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_event(args, xml_out, "comment", writer::XmlEvent::comment(data))
+}
+
+pub(crate) fn write_content<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    content: &str,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_event(
+        args,
+        xml_out,
+        "content",
+        writer::XmlEvent::characters(content),
+    )
+}
+
+pub(crate) fn write_end<O>(args: &Args, xml_out: &mut xml::EventWriter<&mut O>) -> Result<()>
+where
+    O: io::Write,
+{
+    write_event(
+        args,
+        xml_out,
+        "end-tag",
+        writer::XmlEvent::end_element().into(),
+    )
+}
+
+pub(crate) fn write_tag<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    element: &str,
+    content: &str,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, element)?;
+    write_content(args, xml_out, content)?;
+    write_end(args, xml_out)?;
+    Ok(())
+}
+
+fn write_access<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    access: Option<Access>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    match access {
+        Some(access) => write_tag(args, xml_out, "access", access.as_str()),
+        None => Ok(()),
+    }
+}
+
+/// Write the side effects of writing and reading a register or field.
+fn write_side_effects<O>(
+    args: &Args,
+    xml_out: &mut xml::EventWriter<&mut O>,
+    modified_write_values: Option<ModifiedWriteValues>,
+    read_action: Option<ReadAction>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    if let Some(modified_write_values) = modified_write_values {
+        write_tag(
+            args,
+            xml_out,
+            "modifiedWriteValues",
+            modified_write_values.as_str(),
+        )?;
+    }
+    if let Some(read_action) = read_action {
+        write_tag(args, xml_out, "readAction", read_action.as_str())?;
+    }
+    Ok(())
+}
+
+/// Write the SVD <device> header fields, up to the peripherals.
+fn write_device_header<O>(
+    args: &Args,
+    device: &Device,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    let cpu = &device.cpu;
+
+    write_tag(args, xml_out, "name", &device.name)?;
+    write_tag(args, xml_out, "version", &device.version)?;
+    write_tag(args, xml_out, "description", &device.description)?;
+    write_start(args, xml_out, "cpu")?;
+    write_tag(args, xml_out, "name", &cpu.name)?;
+    write_tag(args, xml_out, "revision", &cpu.revision)?;
+    write_tag(args, xml_out, "endian", &cpu.endian)?;
+    write_tag(args, xml_out, "mpuPresent", &cpu.mpu_present.to_string())?;
+    write_tag(args, xml_out, "fpuPresent", &cpu.fpu_present.to_string())?;
+    write_tag(
+        args,
+        xml_out,
+        "nvicPrioBits",
+        &cpu.nvic_prio_bits.to_string(),
+    )?;
+    write_tag(
+        args,
+        xml_out,
+        "vendorSystickConfig",
+        &cpu.vendor_systick_config.to_string(),
+    )?;
+    write_end(args, xml_out)?;
+    write_tag(
+        args,
+        xml_out,
+        "addressUnitBits",
+        &device.address_unit_bits.to_string(),
+    )?;
+    write_tag(args, xml_out, "width", &device.width.to_string())?;
+    write_tag(args, xml_out, "size", &device.size.to_string())?;
+    write_tag(args, xml_out, "access", device.access.as_str())?;
+    write_tag(
+        args,
+        xml_out,
+        "resetValue",
+        &format!("0x{:08X}", device.reset_value),
+    )?;
+    write_tag(
+        args,
+        xml_out,
+        "resetMask",
+        &format!("0x{:08X}", device.reset_mask),
+    )
+}
+
+/// Write a device as SVD.
+pub fn write_device<O>(
+    args: &Args,
+    device: &Device,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, "device")?;
+    write_comment(
+        args,
+        xml_out,
+        "Created by tixml2svd; https://github.com/dhoove/tixml2svd",
+    )?;
+
+    if !args.no_device_info {
+        write_device_header(args, device, xml_out)?;
+    }
+
+    if !device.peripherals.is_empty() {
+        write_start(args, xml_out, "peripherals")?;
+        for peripheral in &device.peripherals {
+            let original = peripheral
+                .derived_from
+                .as_ref()
+                .and_then(|original| device.peripherals.iter().find(|p| &p.name == original));
+            write_peripheral_in(args, peripheral, original, xml_out)?;
+        }
+        write_end(args, xml_out)?;
+    }
+
+    write_end(args, xml_out)
+}
+
+/// Write a peripheral as SVD.
+pub fn write_peripheral<O>(
+    args: &Args,
+    peripheral: &Peripheral,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_peripheral_in(args, peripheral, None, xml_out)
+}
+
+/// Write a peripheral as SVD, given the original of a derived one.
+fn write_peripheral_in<O>(
+    args: &Args,
+    peripheral: &Peripheral,
+    original: Option<&Peripheral>,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    let derived_from = peripheral.derived_from.as_deref();
+    write_start_derived(args, xml_out, "peripheral", derived_from)?;
+    write_tag(args, xml_out, "name", &peripheral.name)?;
+    if derived_from.is_some() {
+        if let Some(ref description) = peripheral.description {
+            if original.is_none_or(|o| o.description.as_ref() != Some(description)) {
+                write_tag(args, xml_out, "description", description)?;
+            }
+        }
+        // Everything else is taken from the original peripheral
+        if let Some(base_address) = peripheral.base_address {
+            write_tag(
+                args,
+                xml_out,
+                "baseAddress",
+                &format!("0x{:08X}", base_address),
+            )?;
+        }
+        for interrupt in &peripheral.interrupts {
+            write_interrupt(args, interrupt, xml_out)?;
+        }
+        return write_end(args, xml_out);
+    }
+    if let Some(ref value) = peripheral.value {
+        write_tag(args, xml_out, "value", value)?;
+    }
+    if let Some(ref description) = peripheral.description {
+        write_tag(args, xml_out, "description", description)?;
+    }
+    if let Some(ref group_name) = peripheral.group_name {
+        write_tag(args, xml_out, "groupName", group_name)?;
+    }
+    if let Some(base_address) = peripheral.base_address {
+        write_tag(
+            args,
+            xml_out,
+            "baseAddress",
+            &format!("0x{:08X}", base_address),
+        )?;
+    }
+    if let Some(size) = peripheral.size {
+        write_start(args, xml_out, "addressBlock")?;
+        write_tag(args, xml_out, "offset", "0")?;
+        write_tag(args, xml_out, "size", &format!("0x{:X}", size))?;
+        write_tag(args, xml_out, "usage", "registers")?;
+        write_end(args, xml_out)?;
+    }
+    for interrupt in &peripheral.interrupts {
+        write_interrupt(args, interrupt, xml_out)?;
+    }
+
+    if !peripheral.registers.is_empty() {
+        write_start(args, xml_out, "registers")?;
+        for register in &peripheral.registers {
+            write_register(args, register, xml_out)?;
+        }
+        write_end(args, xml_out)?;
+    }
+
+    write_end(args, xml_out)
+}
+
+fn write_interrupt<O>(
+    args: &Args,
+    interrupt: &Interrupt,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, "interrupt")?;
+    write_tag(args, xml_out, "name", &interrupt.name)?;
+    if let Some(ref description) = interrupt.description {
+        write_tag(args, xml_out, "description", description)?;
+    }
+    write_tag(args, xml_out, "value", &interrupt.value.to_string())?;
+    write_end(args, xml_out)
+}
+
+fn write_register<O>(
+    args: &Args,
+    register: &Register,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, "register")?;
+    if let Some(ref dim) = register.dim {
+        write_tag(args, xml_out, "dim", &dim.dim.to_string())?;
+        write_tag(
+            args,
+            xml_out,
+            "dimIncrement",
+            &format!("0x{:X}", dim.dim_increment),
+        )?;
+        if let Some(ref index) = dim.dim_index {
+            write_tag(args, xml_out, "dimIndex", index)?;
+        }
+    }
+    if !register.name.is_empty() {
+        write_tag(args, xml_out, "name", &register.name)?;
+    }
+    if let Some(ref value) = register.value {
+        write_tag(args, xml_out, "value", value)?;
+    }
+    write_tag(
+        args,
+        xml_out,
+        "description",
+        register.description.as_deref().unwrap_or("--"),
+    )?;
+    write_tag(
+        args,
+        xml_out,
+        "addressOffset",
+        &format!("0x{:X}", register.address_offset),
+    )?;
+    if let Some(size) = register.size {
+        write_tag(args, xml_out, "size", &size.to_string())?;
+    }
+    write_access(args, xml_out, register.access)?;
+    match register.reset_value {
+        Some(value) => write_tag(args, xml_out, "resetValue", &format!("0x{:X}", value))?,
+        // For svd2rust
+        None => write_tag(args, xml_out, "resetValue", "0")?,
+    }
+    write_side_effects(
+        args,
+        xml_out,
+        register.modified_write_values,
+        register.read_action,
+    )?;
+
+    if !register.fields.is_empty() {
+        write_start(args, xml_out, "fields")?;
+        for field in &register.fields {
+            write_field(args, field, register.access, xml_out)?;
+        }
+        write_end(args, xml_out)?;
+    }
+
+    write_end(args, xml_out)
+}
+
+/// Write a field, whose access defaults to `register_access`.
+fn write_field<O>(
+    args: &Args,
+    field: &Field,
+    register_access: Option<Access>,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, "field")?;
+    if !field.name.is_empty() {
+        write_tag(args, xml_out, "name", &field.name)?;
+    }
+    if let Some(ref description) = field.description {
+        match (field.bit_offset, field.bit_width) {
+            (Some(offset), Some(width)) => {
+                let begin = (offset + width).saturating_sub(1);
+                let desc = format!("[{}:{}] {}", begin, offset, description);
+                write_tag(args, xml_out, "description", &desc)?;
+            }
+            _ => write_tag(
+                args,
+                xml_out,
+                "description",
+                if description.is_empty() {
+                    "--"
+                } else {
+                    description
+                },
+            )?,
+        }
+    }
+    if let Some(offset) = field.bit_offset {
+        write_tag(args, xml_out, "bitOffset", &offset.to_string())?;
+    }
+    if let Some(width) = field.bit_width {
+        write_tag(args, xml_out, "bitWidth", &width.to_string())?;
+    }
+    if let Some(ref range) = field.bit_range {
+        write_tag(args, xml_out, "bitRange", range)?;
+    }
+    write_access(args, xml_out, field.access)?;
+    write_side_effects(
+        args,
+        xml_out,
+        field.modified_write_values,
+        field.read_action,
+    )?;
+
+    let usage = field.access.or(register_access).map(Usage::from_access);
+    if let Some(ref derived_from) = field.enumerated_values_derived_from {
+        write_start_derived(args, xml_out, "enumeratedValues", Some(derived_from))?;
+        write_end(args, xml_out)?;
+    } else if field.write_enumerated_values.is_empty() {
+        write_enumerated_values(
+            args,
+            field.enumerated_values_name.as_deref(),
+            usage,
+            &field.enumerated_values,
+            xml_out,
+        )?;
+    } else {
+        write_enumerated_values(
+            args,
+            None,
+            Some(Usage::Read),
+            &field.enumerated_values,
+            xml_out,
+        )?;
+        write_enumerated_values(
+            args,
+            None,
+            Some(Usage::Write),
+            &field.write_enumerated_values,
+            xml_out,
+        )?;
+    }
+
+    write_end(args, xml_out)
+}
+
+/// Write an `<enumeratedValues>` element, unless there are no values.
+fn write_enumerated_values<O>(
+    args: &Args,
+    name: Option<&str>,
+    usage: Option<Usage>,
+    values: &[EnumeratedValue],
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    if values.is_empty() {
+        return Ok(());
+    }
+    write_start(args, xml_out, "enumeratedValues")?;
+    if let Some(name) = name {
+        write_tag(args, xml_out, "name", name)?;
+    }
+    if let Some(usage) = usage {
+        write_tag(args, xml_out, "usage", usage.as_str())?;
+    }
+    for value in values {
+        write_enumerated_value(args, value, xml_out)?;
+    }
+    write_end(args, xml_out)
+}
+
+fn write_enumerated_value<O>(
+    args: &Args,
+    value: &EnumeratedValue,
+    xml_out: &mut xml::EventWriter<&mut O>,
+) -> Result<()>
+where
+    O: io::Write,
+{
+    write_start(args, xml_out, "enumeratedValue")?;
+    if !value.name.is_empty() {
+        write_tag(args, xml_out, "name", &value.name)?;
+    }
+    if let Some(ref description) = value.description {
+        write_tag(
+            args,
+            xml_out,
+            "description",
+            if description.is_empty() {
+                "--"
+            } else {
+                description
+            },
+        )?;
+    }
+    write_tag(args, xml_out, "value", &value.value)?;
+    write_end(args, xml_out)
+}
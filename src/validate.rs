@@ -0,0 +1,415 @@
+//! Checking a converted device for problems that SVD consumers such as
+//! svd2rust reject.
+
+use crate::error::{Error, Result, Warning};
+use crate::model::{Device, Field, Peripheral, Register};
+use crate::reader::parse_number;
+
+fn name_not_unique(name: &str) -> Warning {
+    Warning::NameNotUnique {
+        context: Box::default(),
+        name: name.to_string(),
+    }
+}
+
+fn overlap(other: String) -> Warning {
+    Warning::Overlap {
+        context: Box::default(),
+        other,
+    }
+}
+
+/// Whether the half-open ranges `a` and `b` overlap.
+fn overlaps(a: (u64, u64), b: (u64, u64)) -> bool {
+    a.0 < b.1 && b.0 < a.1
+}
+
+/// The address ranges of a register, one per array element.
+fn register_ranges(device: &Device, register: &Register) -> Vec<(u64, u64)> {
+    let bits = u64::from(register.size.unwrap_or(device.size));
+    let unit = u64::from(device.address_unit_bits.max(1));
+    let bytes = bits.div_ceil(unit).max(1);
+    let (count, increment) = match register.dim {
+        Some(ref dim) => (u64::from(dim.dim), dim.dim_increment),
+        None => (1, 0),
+    };
+    (0..count)
+        .map(|i| {
+            let start = register.address_offset + i * increment;
+            (start, start + bytes)
+        })
+        .collect()
+}
+
+/// Fail on the first field that does not fit into its register, given
+/// the default register size. This is checked after patches are
+/// applied, so that a patch can still fix such a field.
+pub(crate) fn check_field_sizes(peripheral: &Peripheral, default_size: u32) -> Result<()> {
+    for register in &peripheral.registers {
+        let register_width = register.size.unwrap_or(default_size);
+        for field in &register.fields {
+            if let (Some(offset), Some(width)) = (field.bit_offset, field.bit_width) {
+                if u64::from(offset) + u64::from(width) > u64::from(register_width) {
+                    return Err(Error::FieldTooBig {
+                        context: Box::default(),
+                        bit_offset: offset,
+                        bit_width: width,
+                        register_width,
+                    }
+                    .in_field(&field.name)
+                    .in_register(&register.name)
+                    .in_peripheral(&peripheral.name));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_field(field: &Field, register_width: u32, errors: &mut Vec<Warning>) {
+    let (offset, width) = match (field.bit_offset, field.bit_width) {
+        (Some(offset), Some(width)) => (offset, width),
+        _ => return,
+    };
+    if u64::from(offset) + u64::from(width) > u64::from(register_width) {
+        errors.push(
+            Error::FieldTooBig {
+                context: Box::default(),
+                bit_offset: offset,
+                bit_width: width,
+                register_width,
+            }
+            .into(),
+        );
+    }
+
+    for value in field.all_enumerated_values() {
+        match parse_number("value", &value.value) {
+            Ok(number) if width < 64 && number >> width != 0 => {
+                errors.push(Warning::EnumValueTooBig {
+                    context: Box::default(),
+                    value: value.value.clone(),
+                    bit_width: width,
+                })
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(e.into()),
+        }
+    }
+}
+
+fn validate_register(device: &Device, register: &Register) -> Vec<Warning> {
+    let mut errors = vec![];
+    let register_width = register.size.unwrap_or(device.size);
+
+    for (i, field) in register.fields.iter().enumerate() {
+        let mut field_errors = vec![];
+        validate_field(field, register_width, &mut field_errors);
+
+        if register.fields[..i].iter().any(|f| f.name == field.name) {
+            field_errors.push(name_not_unique(&field.name));
+        }
+        if let (Some(offset), Some(width)) = (field.bit_offset, field.bit_width) {
+            let bits = (u64::from(offset), u64::from(offset) + u64::from(width));
+            for other in &register.fields[..i] {
+                if let (Some(o_offset), Some(o_width)) = (other.bit_offset, other.bit_width) {
+                    let other_bits = (
+                        u64::from(o_offset),
+                        u64::from(o_offset) + u64::from(o_width),
+                    );
+                    if overlaps(bits, other_bits) {
+                        field_errors.push(overlap(format!("field {}", other.name)));
+                    }
+                }
+            }
+        }
+        errors.extend(field_errors.into_iter().map(|e| e.in_field(&field.name)));
+    }
+    errors
+}
+
+fn validate_peripheral(device: &Device, peripheral: &Peripheral) -> Vec<Warning> {
+    let mut errors = vec![];
+    let ranges: Vec<Vec<(u64, u64)>> = peripheral
+        .registers
+        .iter()
+        .map(|r| register_ranges(device, r))
+        .collect();
+
+    for (i, register) in peripheral.registers.iter().enumerate() {
+        let mut register_errors = validate_register(device, register);
+        if peripheral.registers[..i]
+            .iter()
+            .any(|r| r.name == register.name)
+        {
+            register_errors.push(name_not_unique(&register.name));
+        }
+        for (j, other) in peripheral.registers[..i].iter().enumerate() {
+            let overlapping = ranges[i]
+                .iter()
+                .any(|a| ranges[j].iter().any(|b| overlaps(*a, *b)));
+            if overlapping {
+                register_errors.push(overlap(format!("register {}", other.name)));
+            }
+        }
+        errors.extend(
+            register_errors
+                .into_iter()
+                .map(|e| e.in_register(&register.name)),
+        );
+    }
+    errors
+}
+
+/// Check a device for overlapping peripherals, registers and fields,
+/// names used twice among them, fields beyond the size of their
+/// register, and enumerated values that do not fit into their field.
+/// Derived peripherals are only checked for
+/// overlaps, as their registers are those of the original.
+pub fn validate_device(device: &Device) -> Vec<Warning> {
+    let mut errors = vec![];
+    for (i, peripheral) in device.peripherals.iter().enumerate() {
+        let mut peripheral_errors = if peripheral.derived_from.is_none() {
+            validate_peripheral(device, peripheral)
+        } else {
+            vec![]
+        };
+        if device.peripherals[..i]
+            .iter()
+            .any(|p| p.name == peripheral.name)
+        {
+            peripheral_errors.push(name_not_unique(&peripheral.name));
+        }
+
+        if let (Some(base), Some(size)) = (peripheral.base_address, peripheral.size) {
+            for other in &device.peripherals[..i] {
+                if let (Some(o_base), Some(o_size)) = (other.base_address, other.size) {
+                    if overlaps((base, base + size), (o_base, o_base + o_size)) {
+                        peripheral_errors.push(overlap(format!("peripheral {}", other.name)));
+                    }
+                }
+            }
+        }
+        errors.extend(
+            peripheral_errors
+                .into_iter()
+                .map(|e| e.in_peripheral(&peripheral.name)),
+        );
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::EnumeratedValue;
+
+    fn field(name: &str, bit_offset: u32, bit_width: u32) -> Field {
+        Field {
+            name: name.to_string(),
+            bit_offset: Some(bit_offset),
+            bit_width: Some(bit_width),
+            ..Field::default()
+        }
+    }
+
+    fn register(name: &str, address_offset: u64, fields: Vec<Field>) -> Register {
+        Register {
+            name: name.to_string(),
+            address_offset,
+            fields,
+            ..Register::default()
+        }
+    }
+
+    fn peripheral(name: &str, base_address: u64, registers: Vec<Register>) -> Peripheral {
+        Peripheral {
+            name: name.to_string(),
+            base_address: Some(base_address),
+            size: Some(0x100),
+            registers,
+            ..Peripheral::default()
+        }
+    }
+
+    fn device(peripherals: Vec<Peripheral>) -> Device {
+        Device {
+            peripherals,
+            ..Device::default()
+        }
+    }
+
+    /// The warnings about a device, as text.
+    fn warnings(device: &Device) -> Vec<String> {
+        validate_device(device)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn accept_valid_device() {
+        let device = device(vec![
+            peripheral(
+                "GPIO",
+                0x4000_0000,
+                vec![
+                    register("DIN", 0, vec![field("PIN0", 0, 1), field("PIN1", 1, 1)]),
+                    register("DOUT", 4, vec![field("PINS", 0, 32)]),
+                ],
+            ),
+            peripheral("UART0", 0x4000_0100, vec![]),
+        ]);
+        assert!(warnings(&device).is_empty());
+    }
+
+    #[test]
+    fn report_overlapping_fields() {
+        let device = device(vec![peripheral(
+            "GPIO",
+            0,
+            vec![register(
+                "DIN",
+                0,
+                vec![field("LOW", 0, 4), field("MID", 2, 4)],
+            )],
+        )]);
+        assert_eq!(
+            warnings(&device),
+            ["peripheral GPIO, register DIN, field MID: overlaps field LOW"]
+        );
+    }
+
+    #[test]
+    fn report_overlapping_registers() {
+        let mut wide = register("WIDE", 0, vec![]);
+        wide.size = Some(64);
+        let device = device(vec![peripheral(
+            "GPIO",
+            0,
+            vec![wide, register("DIN", 4, vec![])],
+        )]);
+        assert_eq!(
+            warnings(&device),
+            ["peripheral GPIO, register DIN: overlaps register WIDE"]
+        );
+    }
+
+    #[test]
+    fn report_overlapping_array_elements() {
+        let mut array = register("DOUT%s", 0, vec![]);
+        array.dim = Some(crate::model::Dim {
+            dim: 4,
+            dim_increment: 4,
+            dim_index: None,
+        });
+        let device = device(vec![peripheral(
+            "GPIO",
+            0,
+            vec![array, register("CTL", 8, vec![])],
+        )]);
+        assert_eq!(
+            warnings(&device),
+            ["peripheral GPIO, register CTL: overlaps register DOUT%s"]
+        );
+    }
+
+    #[test]
+    fn report_overlapping_peripherals() {
+        let device = device(vec![
+            peripheral("GPIO", 0x4000_0000, vec![]),
+            peripheral("UART0", 0x4000_0080, vec![]),
+        ]);
+        assert_eq!(
+            warnings(&device),
+            ["peripheral UART0: overlaps peripheral GPIO"]
+        );
+    }
+
+    #[test]
+    fn report_sizes() {
+        let mut mode = field("MODE", 0, 2);
+        mode.enumerated_values = vec![
+            EnumeratedValue {
+                name: "FAST".to_string(),
+                description: None,
+                value: "0x3".to_string(),
+            },
+            EnumeratedValue {
+                name: "OFF".to_string(),
+                description: None,
+                value: "0x4".to_string(),
+            },
+        ];
+        let mut short = register("SHORT", 4, vec![field("DATA", 12, 8)]);
+        short.size = Some(16);
+        let device = device(vec![peripheral(
+            "GPIO",
+            0,
+            vec![register("CTL", 0, vec![mode]), short],
+        )]);
+        assert_eq!(
+            warnings(&device),
+            [
+                "peripheral GPIO, register CTL, field MODE: \
+                 enumerated value 0x4 too big for field of width 2",
+                "peripheral GPIO, register SHORT, field DATA: \
+                 field with offset 12 and width 8 too big for register of width 16",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_duplicate_names() {
+        let device = device(vec![
+            peripheral(
+                "GPIO",
+                0,
+                vec![
+                    register("DIN", 0, vec![field("PIN", 0, 1), field("PIN", 1, 1)]),
+                    register("DIN", 4, vec![]),
+                ],
+            ),
+            peripheral("GPIO", 0x100, vec![]),
+        ]);
+        assert_eq!(
+            warnings(&device),
+            [
+                "peripheral GPIO, register DIN, field PIN: name PIN is not unique",
+                "peripheral GPIO, register DIN: name DIN is not unique",
+                "peripheral GPIO: name GPIO is not unique",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_derived_peripherals_for_overlaps_only() {
+        let mut derived = peripheral(
+            "GPIO1",
+            0x80,
+            vec![register("DIN", 0, vec![field("PIN", 0, 64)])],
+        );
+        derived.derived_from = Some("GPIO0".to_string());
+        let device = device(vec![peripheral("GPIO0", 0, vec![]), derived]);
+        assert_eq!(
+            warnings(&device),
+            ["peripheral GPIO1: overlaps peripheral GPIO0"]
+        );
+    }
+
+    #[test]
+    fn check_field_sizes_after_patching() {
+        let mut short = register("SHORT", 0, vec![field("DATA", 12, 8)]);
+        short.size = Some(16);
+        let gpio = peripheral("GPIO", 0, vec![short]);
+        let error = check_field_sizes(&gpio, 32).unwrap_err();
+        assert_eq!(error.context().field.as_deref(), Some("DATA"));
+        let gpio = peripheral(
+            "GPIO",
+            0,
+            vec![register("DIN", 0, vec![field("DATA", 12, 8)])],
+        );
+        assert!(check_field_sizes(&gpio, 32).is_ok());
+        assert!(check_field_sizes(&gpio, 16).is_err());
+    }
+}
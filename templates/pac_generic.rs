@@ -0,0 +1,515 @@
+//! Common register and bit access and modify traits, compatible with the
+//! API of svd2rust generated crates.
+
+use core::marker;
+
+/// Raw register type (`u8`, `u16`, `u32`, ...)
+pub trait RawReg:
+    Copy
+    + Default
+    + From<bool>
+    + core::ops::BitOr<Output = Self>
+    + core::ops::BitAnd<Output = Self>
+    + core::ops::BitOrAssign
+    + core::ops::BitAndAssign
+    + core::ops::Not<Output = Self>
+    + core::ops::Shl<u8, Output = Self>
+{
+    /// Mask for bits of width `WI`
+    fn mask<const WI: u8>() -> Self;
+    /// Mask for bits of width 1
+    const ONE: Self;
+}
+
+macro_rules! raw_reg {
+    ($U:ty, $size:literal, $mask:ident) => {
+        impl RawReg for $U {
+            #[inline(always)]
+            fn mask<const WI: u8>() -> Self {
+                $mask::<WI>()
+            }
+            const ONE: Self = 1;
+        }
+        const fn $mask<const WI: u8>() -> $U {
+            <$U>::MAX >> ($size - WI)
+        }
+        impl FieldSpec for $U {
+            type Ux = $U;
+        }
+    };
+}
+
+raw_reg!(u8, 8, mask_u8);
+raw_reg!(u16, 16, mask_u16);
+raw_reg!(u32, 32, mask_u32);
+raw_reg!(u64, 64, mask_u64);
+
+/// Raw register type
+pub trait RegisterSpec {
+    /// Raw register type (`u8`, `u16`, `u32`, ...).
+    type Ux: RawReg;
+}
+
+/// Raw field type
+pub trait FieldSpec: Sized {
+    /// Raw field type (`u8`, `u16`, `u32`, ...).
+    type Ux: Copy + PartialEq + From<Self>;
+}
+
+/// Trait implemented by readable registers to enable the `read` method.
+pub trait Readable: RegisterSpec {}
+
+/// Trait implemented by writeable registers.
+///
+/// This enables the `write`, `write_with_zero` and `reset` methods.
+pub trait Writable: RegisterSpec {
+    /// Specifies the register bits that are not changed if you pass `1`
+    /// and are changed if you pass `0`
+    const ZERO_TO_MODIFY_FIELDS_BITMAP: Self::Ux;
+
+    /// Specifies the register bits that are not changed if you pass `0`
+    /// and are changed if you pass `1`
+    const ONE_TO_MODIFY_FIELDS_BITMAP: Self::Ux;
+}
+
+/// Reset value of the register.
+///
+/// This value is the initial value for the `write` method. It can also
+/// be directly written to the register by using the `reset` method.
+pub trait Resettable: RegisterSpec {
+    /// Reset value of the register.
+    const RESET_VALUE: Self::Ux;
+
+    /// Reset value of the register.
+    #[inline(always)]
+    fn reset_value() -> Self::Ux {
+        Self::RESET_VALUE
+    }
+}
+
+/// This structure provides volatile access to registers.
+#[repr(transparent)]
+pub struct Reg<REG: RegisterSpec> {
+    register: vcell::VolatileCell<REG::Ux>,
+    _marker: marker::PhantomData<REG>,
+}
+
+unsafe impl<REG: RegisterSpec> Send for Reg<REG> where REG::Ux: Send {}
+
+impl<REG: RegisterSpec> Reg<REG> {
+    /// Returns the underlying memory address of the register.
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *mut REG::Ux {
+        self.register.as_ptr()
+    }
+}
+
+impl<REG: Readable> Reg<REG> {
+    /// Reads the contents of a `Readable` register.
+    ///
+    /// You can read the raw contents of a register by using `bits`:
+    /// ```ignore
+    /// let bits = periph.reg.read().bits();
+    /// ```
+    /// or get the content of a particular field of a register:
+    /// ```ignore
+    /// let reader = periph.reg.read();
+    /// let bits = reader.field1().bits();
+    /// let flag = reader.field2().bit_is_set();
+    /// ```
+    #[inline(always)]
+    pub fn read(&self) -> R<REG> {
+        R {
+            bits: self.register.get(),
+            _reg: marker::PhantomData,
+        }
+    }
+}
+
+impl<REG: Resettable + Writable> Reg<REG> {
+    /// Writes the reset value to a `Writable` register.
+    #[inline(always)]
+    pub fn reset(&self) {
+        self.register.set(REG::RESET_VALUE)
+    }
+
+    /// Writes bits to a `Writable` register, starting from its reset
+    /// value.
+    ///
+    /// ```ignore
+    /// periph.reg.write(|w| w.field1().bits(1).field2().set_bit());
+    /// ```
+    #[inline(always)]
+    pub fn write<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<REG>) -> &mut W<REG>,
+    {
+        self.register.set(
+            f(&mut W {
+                bits: REG::RESET_VALUE & !REG::ONE_TO_MODIFY_FIELDS_BITMAP
+                    | REG::ZERO_TO_MODIFY_FIELDS_BITMAP,
+                _reg: marker::PhantomData,
+            })
+            .bits,
+        );
+    }
+}
+
+impl<REG: Writable> Reg<REG> {
+    /// Writes bits to a `Writable` register, starting from zero.
+    ///
+    /// # Safety
+    ///
+    /// Unsafe, as fields not written get the value 0, which may not be
+    /// valid for them.
+    #[inline(always)]
+    pub unsafe fn write_with_zero<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W<REG>) -> &mut W<REG>,
+    {
+        self.register.set(
+            f(&mut W {
+                bits: REG::Ux::default(),
+                _reg: marker::PhantomData,
+            })
+            .bits,
+        );
+    }
+}
+
+impl<REG: Readable + Writable> Reg<REG> {
+    /// Modifies the contents of the register by reading and then
+    /// writing it.
+    ///
+    /// ```ignore
+    /// periph.reg.modify(|r, w| w.field1().bits(r.field1().bits() + 1));
+    /// ```
+    #[inline(always)]
+    pub fn modify<F>(&self, f: F)
+    where
+        for<'w> F: FnOnce(&R<REG>, &'w mut W<REG>) -> &'w mut W<REG>,
+    {
+        let bits = self.register.get();
+        self.register.set(
+            f(
+                &R {
+                    bits,
+                    _reg: marker::PhantomData,
+                },
+                &mut W {
+                    bits: bits & !REG::ONE_TO_MODIFY_FIELDS_BITMAP
+                        | REG::ZERO_TO_MODIFY_FIELDS_BITMAP,
+                    _reg: marker::PhantomData,
+                },
+            )
+            .bits,
+        );
+    }
+}
+
+/// Register reader.
+///
+/// Result of the `read` methods of registers. Also used as a closure
+/// argument in the `modify` method.
+pub struct R<REG: RegisterSpec> {
+    pub(crate) bits: REG::Ux,
+    _reg: marker::PhantomData<REG>,
+}
+
+impl<REG: RegisterSpec> R<REG> {
+    /// Reads raw bits from register.
+    #[inline(always)]
+    pub const fn bits(&self) -> REG::Ux {
+        self.bits
+    }
+}
+
+impl<REG: RegisterSpec> PartialEq<REG::Ux> for R<REG>
+where
+    REG::Ux: PartialEq,
+{
+    #[inline(always)]
+    fn eq(&self, other: &REG::Ux) -> bool {
+        self.bits.eq(other)
+    }
+}
+
+/// Register writer.
+///
+/// Used as an argument to the closures in the `write` and `modify`
+/// methods of the register.
+pub struct W<REG: RegisterSpec> {
+    /// Writable bits
+    pub(crate) bits: REG::Ux,
+    _reg: marker::PhantomData<REG>,
+}
+
+impl<REG: Writable> W<REG> {
+    /// Writes raw bits to the register.
+    ///
+    /// # Safety
+    ///
+    /// Passing incorrect value can cause undefined behaviour. See
+    /// reference manual.
+    #[inline(always)]
+    pub unsafe fn bits(&mut self, bits: REG::Ux) -> &mut Self {
+        self.bits = bits;
+        self
+    }
+}
+
+/// Field reader.
+///
+/// Result of the `read` methods of fields.
+pub struct FieldReader<FI = u8>
+where
+    FI: FieldSpec,
+{
+    pub(crate) bits: FI::Ux,
+    _reg: marker::PhantomData<FI>,
+}
+
+impl<FI: FieldSpec> FieldReader<FI> {
+    /// Creates a new instance of the reader.
+    #[allow(unused)]
+    #[inline(always)]
+    pub(crate) const fn new(bits: FI::Ux) -> Self {
+        Self {
+            bits,
+            _reg: marker::PhantomData,
+        }
+    }
+
+    /// Reads raw bits from field.
+    #[inline(always)]
+    pub const fn bits(&self) -> FI::Ux {
+        self.bits
+    }
+}
+
+impl<FI> PartialEq<FI> for FieldReader<FI>
+where
+    FI: FieldSpec + Copy,
+{
+    #[inline(always)]
+    fn eq(&self, other: &FI) -> bool {
+        self.bits.eq(&FI::Ux::from(*other))
+    }
+}
+
+/// Single bit reader.
+///
+/// Result of the `read` methods of fields.
+pub struct BitReader<FI = bool> {
+    pub(crate) bits: bool,
+    _reg: marker::PhantomData<FI>,
+}
+
+impl<FI> BitReader<FI> {
+    /// Creates a new instance of the reader.
+    #[allow(unused)]
+    #[inline(always)]
+    pub(crate) const fn new(bits: bool) -> Self {
+        Self {
+            bits,
+            _reg: marker::PhantomData,
+        }
+    }
+
+    /// Value of the field as raw bits.
+    #[inline(always)]
+    pub const fn bit(&self) -> bool {
+        self.bits
+    }
+
+    /// Returns `true` if the bit is clear (0).
+    #[inline(always)]
+    pub const fn bit_is_clear(&self) -> bool {
+        !self.bit()
+    }
+
+    /// Returns `true` if the bit is set (1).
+    #[inline(always)]
+    pub const fn bit_is_set(&self) -> bool {
+        self.bit()
+    }
+}
+
+impl<FI> PartialEq<FI> for BitReader<FI>
+where
+    FI: Copy,
+    bool: From<FI>,
+{
+    #[inline(always)]
+    fn eq(&self, other: &FI) -> bool {
+        self.bits.eq(&bool::from(*other))
+    }
+}
+
+/// Marker for field writers whose `bits` method is safe.
+pub struct Safe;
+/// Marker for field writers whose `bits` method is unsafe.
+pub struct Unsafe;
+
+/// Field writer.
+///
+/// Returned by the field methods of a register writer.
+pub struct FieldWriter<'a, REG, const WI: u8, FI = u8, Safety = Unsafe>
+where
+    REG: Writable + RegisterSpec,
+    FI: FieldSpec,
+{
+    pub(crate) w: &'a mut W<REG>,
+    pub(crate) o: u8,
+    _field: marker::PhantomData<(FI, Safety)>,
+}
+
+impl<'a, REG, const WI: u8, FI, Safety> FieldWriter<'a, REG, WI, FI, Safety>
+where
+    REG: Writable + RegisterSpec,
+    FI: FieldSpec,
+{
+    /// Creates a new instance of the writer.
+    #[allow(unused)]
+    #[inline(always)]
+    pub(crate) fn new(w: &'a mut W<REG>, o: u8) -> Self {
+        Self {
+            w,
+            o,
+            _field: marker::PhantomData,
+        }
+    }
+
+    /// Field width.
+    pub const WIDTH: u8 = WI;
+
+    /// Field width.
+    #[inline(always)]
+    pub const fn width(&self) -> u8 {
+        WI
+    }
+
+    /// Field offset.
+    #[inline(always)]
+    pub const fn offset(&self) -> u8 {
+        self.o
+    }
+}
+
+impl<'a, REG, const WI: u8, FI> FieldWriter<'a, REG, WI, FI, Unsafe>
+where
+    REG: Writable + RegisterSpec,
+    FI: FieldSpec,
+    REG::Ux: From<FI::Ux>,
+{
+    /// Writes raw bits to the field.
+    ///
+    /// # Safety
+    ///
+    /// Passing incorrect value can cause undefined behaviour. See
+    /// reference manual.
+    #[inline(always)]
+    pub unsafe fn bits(self, value: FI::Ux) -> &'a mut W<REG> {
+        self.w.bits &= !(REG::Ux::mask::<WI>() << self.o);
+        self.w.bits |= (REG::Ux::from(value) & REG::Ux::mask::<WI>()) << self.o;
+        self.w
+    }
+
+    /// Writes `variant` to the field.
+    #[inline(always)]
+    pub fn variant(self, variant: FI) -> &'a mut W<REG> {
+        unsafe { self.bits(FI::Ux::from(variant)) }
+    }
+}
+
+impl<'a, REG, const WI: u8, FI> FieldWriter<'a, REG, WI, FI, Safe>
+where
+    REG: Writable + RegisterSpec,
+    FI: FieldSpec,
+    REG::Ux: From<FI::Ux>,
+{
+    /// Writes raw bits to the field.
+    #[inline(always)]
+    pub fn bits(self, value: FI::Ux) -> &'a mut W<REG> {
+        self.w.bits &= !(REG::Ux::mask::<WI>() << self.o);
+        self.w.bits |= (REG::Ux::from(value) & REG::Ux::mask::<WI>()) << self.o;
+        self.w
+    }
+
+    /// Writes `variant` to the field.
+    #[inline(always)]
+    pub fn variant(self, variant: FI) -> &'a mut W<REG> {
+        self.bits(FI::Ux::from(variant))
+    }
+}
+
+/// Single bit writer.
+///
+/// Returned by the field methods of a register writer.
+pub struct BitWriter<'a, REG, FI = bool>
+where
+    REG: Writable + RegisterSpec,
+    bool: From<FI>,
+{
+    pub(crate) w: &'a mut W<REG>,
+    pub(crate) o: u8,
+    _field: marker::PhantomData<FI>,
+}
+
+impl<'a, REG, FI> BitWriter<'a, REG, FI>
+where
+    REG: Writable + RegisterSpec,
+    bool: From<FI>,
+{
+    /// Creates a new instance of the writer.
+    #[allow(unused)]
+    #[inline(always)]
+    pub(crate) fn new(w: &'a mut W<REG>, o: u8) -> Self {
+        Self {
+            w,
+            o,
+            _field: marker::PhantomData,
+        }
+    }
+
+    /// Field width.
+    pub const WIDTH: u8 = 1;
+
+    /// Field width.
+    #[inline(always)]
+    pub const fn width(&self) -> u8 {
+        Self::WIDTH
+    }
+
+    /// Field offset.
+    #[inline(always)]
+    pub const fn offset(&self) -> u8 {
+        self.o
+    }
+
+    /// Writes bit to the field.
+    #[inline(always)]
+    pub fn bit(self, value: bool) -> &'a mut W<REG> {
+        self.w.bits &= !(REG::Ux::ONE << self.o);
+        self.w.bits |= (REG::Ux::from(value) & REG::Ux::ONE) << self.o;
+        self.w
+    }
+
+    /// Writes `variant` to the field.
+    #[inline(always)]
+    pub fn variant(self, variant: FI) -> &'a mut W<REG> {
+        self.bit(bool::from(variant))
+    }
+
+    /// Sets the field bit.
+    #[inline(always)]
+    pub fn set_bit(self) -> &'a mut W<REG> {
+        self.bit(true)
+    }
+
+    /// Clears the field bit.
+    #[inline(always)]
+    pub fn clear_bit(self) -> &'a mut W<REG> {
+        self.bit(false)
+    }
+}